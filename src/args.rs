@@ -1,10 +1,38 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::{Parser, Subcommand};
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand, ValueEnum};
+use regex::Regex;
+
+use crate::session_buddy::analyze::{GroupBy, SortBy};
+use crate::session_buddy::filter::{Deleted, Filter};
+use crate::session_buddy::lookup::MatchMode;
+use crate::session_buddy::query::Expr;
+use crate::session_buddy::stale::SortBy as StaleSortBy;
+use crate::session_buddy::timeline::{DateField, Period};
+use crate::Error;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
+    /// Format of error messages written to stderr
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub error_format: Format,
+
+    /// Include sessions flagged as deleted
+    #[arg(long, global = true, conflicts_with = "only_deleted")]
+    pub include_deleted: bool,
+
+    /// Only include sessions flagged as deleted
+    #[arg(long, global = true)]
+    pub only_deleted: bool,
+
+    /// Only include sessions and tabs matching an expression, e.g.
+    /// `domain:github.com AND created>2022-01-01 AND NOT pinned`.
+    /// Commands that don't read sessions reject it
+    #[arg(long = "where", global = true, value_name = "EXPR")]
+    pub where_: Option<String>,
+
     #[command(subcommand)]
     pub action: Action
 }
@@ -29,6 +57,41 @@ pub enum Action {
         #[arg(short, long, value_name = "FILENAME")]
         out: Option<PathBuf>,
 
+        /// Only back up the session with this id, can be repeated
+        #[arg(long = "id", value_name = "ID")]
+        ids: Vec<i32>,
+
+        /// Only back up sessions with a name matching this regex
+        #[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+        name: Option<Regex>,
+
+        /// Only back up sessions with this tag, can be repeated
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// Only back up sessions created on or after this day
+        #[arg(long, value_name = "YYYY-MM-DD")]
+        since: Option<NaiveDate>,
+
+        /// Only back up sessions created on or before this day
+        #[arg(long, value_name = "YYYY-MM-DD")]
+        until: Option<NaiveDate>,
+
+        /// Replace URLs, titles, names and tags with pseudonyms and
+        /// remove what identifies the browser, e.g. for bug reports
+        #[arg(long)]
+        redact: bool,
+
+        /// Keep the structure of domains when redacting, e.g.
+        /// `www.example.com` becomes `<hash>.<hash>.com`
+        #[arg(long, requires = "redact")]
+        keep_domains: bool,
+
+        /// Key of the pseudonyms, to get the same ones in several
+        /// backups. A random key is used by default
+        #[arg(long, value_name = "KEY", requires = "redact")]
+        redact_key: Option<String>,
+
         /// Database to backup
         #[arg(value_name = "DATABASE")]
         path: PathBuf,
@@ -56,6 +119,31 @@ pub enum Action {
 
     /// Print some database statistics
     Stats {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        format: DataFormat,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Break down the storage of a database by table and column
+    Du {
+        /// Number of largest sessions to list
+        #[arg(short, long, default_value_t = 10)]
+        top: u32,
+
+        /// Compact the database afterwards. The VACUUM is done on a
+        /// snapshot which replaces the database only if it passes an
+        /// integrity check.
+        #[arg(long)]
+        compact: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        format: Format,
+
         /// Path to database
         #[arg()]
         path: PathBuf
@@ -76,6 +164,249 @@ pub enum Action {
         path: PathBuf
     },
 
+    /// Repair sessions and recompute their window and tab counts
+    Repair {
+        /// Only report what would be changed
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+
+        /// Output format of the report
+        #[arg(short, long, value_enum, default_value_t)]
+        format: Format,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Analyze the URLs stored in a database
+    Analyze {
+        #[command(subcommand)]
+        action: AnalyzeAction
+    },
+
+    /// Suggest names for unnamed sessions from their contents
+    Autoname {
+        /// Apply all suggestions without asking
+        #[arg(short, long, conflicts_with = "dry_run")]
+        yes: bool,
+
+        /// Only print the suggestions
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Show sessions, tabs and domains saved per day, week or month
+    Timeline {
+        /// Length of a period
+        #[arg(short, long, value_enum, default_value_t)]
+        period: Period,
+
+        /// Timestamp of saved sessions to use
+        #[arg(long, value_enum, default_value_t)]
+        by: DateField,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        format: DataFormat,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// List URLs not seen in any session for a while
+    Stale {
+        /// Days since a URL was last seen in a saved or previous
+        /// session
+        #[arg(short, long, default_value_t = 180)]
+        days: i64,
+
+        /// Order of the URLs
+        #[arg(short, long, value_enum, default_value_t)]
+        sort: StaleSortBy,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        format: DataFormat,
+
+        /// Remove the stale tabs from the saved sessions. Sessions
+        /// left without tabs are moved to the trash.
+        #[arg(long)]
+        prune: bool,
+
+        /// Don't ask before pruning
+        #[arg(short, long, requires = "prune")]
+        yes: bool,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Remove every trace of a site from a database
+    Forget {
+        /// Domain, which includes its subdomains, or a regex matched
+        /// against URLs
+        #[arg(value_name = "DOMAIN_OR_REGEX")]
+        site: String,
+
+        /// Don't ask before removing
+        #[arg(short, long, conflicts_with = "dry_run")]
+        yes: bool,
+
+        /// Only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Find the sessions containing a URL
+    Where {
+        /// URL or pattern to look for
+        #[arg()]
+        pattern: String,
+
+        /// How to match the pattern against URLs
+        #[arg(short, long, value_enum, default_value_t)]
+        mode: MatchMode,
+
+        /// Match exact and prefix patterns against URLs as stored,
+        /// instead of canonicalizing both first
+        #[arg(long)]
+        raw: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        format: DataFormat,
+
+        /// Databases and backup files to search. If none are given,
+        /// we're trying to find databases.
+        #[arg()]
+        paths: Vec<PathBuf>
+    },
+
+    /// Maintain a full-text index of a database
+    Index {
+        #[command(subcommand)]
+        action: IndexAction
+    },
+
+    /// Search the full-text index of a database
+    Find {
+        /// Words to look for in tab titles, URLs, session and window
+        /// names and tags, in FTS5 query syntax
+        #[arg()]
+        query: String,
+
+        /// Return at most this many tabs
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: u32,
+
+        /// Path to the index, defaults to the database path with
+        /// `.index` appended
+        #[arg(long)]
+        index: Option<PathBuf>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        format: DataFormat,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Inspect the undo history and restore deleted items
+    Undo {
+        #[command(subcommand)]
+        action: UndoAction
+    },
+
+    /// Inspect previous sessions and promote them to saved sessions
+    Previous {
+        #[command(subcommand)]
+        action: PreviousAction
+    },
+
+    /// Replace parts of the URLs of all tabs matching a regex
+    Rewrite {
+        /// What to replace in URLs and pending URLs
+        #[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+        from: Regex,
+
+        /// Replacement, `$1` or `${name}` insert capture groups
+        #[arg(long, value_name = "TEMPLATE")]
+        to: String,
+
+        /// What to replace in titles
+        #[arg(long, value_name = "REGEX", value_parser = Regex::new, requires = "title_to")]
+        title_from: Option<Regex>,
+
+        /// Replacement in titles
+        #[arg(long, value_name = "TEMPLATE", requires = "title_from")]
+        title_to: Option<String>,
+
+        /// Apply the changes without asking
+        #[arg(short, long, conflicts_with = "dry_run")]
+        yes: bool,
+
+        /// Only print what would change
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Clean up the URLs of all tabs for good
+    RewriteUrls {
+        /// Remove tracking parameters like `utm_*`, `fbclid` and
+        /// `gclid`, as configured in the config file
+        #[arg(long, required = true)]
+        strip_tracking: bool,
+
+        /// Only print what would change
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Rename, merge, split or delete sessions and move tabs
+    Session {
+        #[command(subcommand)]
+        action: SessionAction
+    },
+
+    /// List, add, remove or rename session tags
+    Tag {
+        #[command(subcommand)]
+        action: TagAction
+    },
+
+    /// Extract or strip session thumbnails
+    Thumbnails {
+        #[command(subcommand)]
+        action: ThumbnailsAction
+    },
+
+    /// List, restore or purge sessions flagged as deleted
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction
+    },
+
     /// Validate a database or JSON backup
     Validate {
         #[command(subcommand)]
@@ -83,17 +414,475 @@ pub enum Action {
     },
 }
 
+impl Args {
+    /// Filter selecting the sessions read commands operate on.
+    pub fn filter(&self) -> Result<Filter, Error> {
+        if self.where_.is_some() && !self.action.supports_where() {
+            return Err(Error::Pattern(
+                "--where is not supported by this command".to_string()
+            ));
+        }
+
+        Ok(Filter {
+            deleted: match (self.include_deleted, self.only_deleted) {
+                (_, true) => Deleted::Only,
+                (true, false) => Deleted::Include,
+                (false, false) => Deleted::Exclude
+            },
+            expr: self.where_.as_deref().map(Expr::parse).transpose()?
+        })
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AnalyzeAction {
+    /// Count tabs and sessions per domain, scheme or path prefix
+    Domains {
+        /// What to group URLs by
+        #[arg(short, long, value_enum, default_value_t)]
+        group_by: GroupBy,
+
+        /// Number of path segments when grouping by prefix
+        #[arg(long, default_value_t = 1)]
+        depth: usize,
+
+        /// Only count URLs with this scheme, can be repeated
+        #[arg(long)]
+        scheme: Vec<String>,
+
+        /// Only report groups matching this regular expression
+        #[arg(long = "match", value_name = "REGEX", value_parser = Regex::new)]
+        pattern: Option<Regex>,
+
+        /// Only report groups with at least this many tabs
+        #[arg(long, default_value_t = 1)]
+        min_count: u64,
+
+        /// Order of the groups
+        #[arg(short, long, value_enum, default_value_t)]
+        sort: SortBy,
+
+        /// Reverse the order
+        #[arg(short, long)]
+        reverse: bool,
+
+        /// Report at most this many groups
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        format: DataFormat,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IndexAction {
+    /// Create or update the index. Only sessions modified since the
+    /// last build are indexed again.
+    Build {
+        /// Index all sessions from scratch
+        #[arg(long)]
+        rebuild: bool,
+
+        /// Path to the index, defaults to the database path with
+        /// `.index` appended
+        #[arg(long)]
+        index: Option<PathBuf>,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum UndoAction {
+    /// List entries of the undo history
+    List {
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Restore the session, window or tab deleted by an undo entry
+    /// as new saved session
+    Restore {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Id of the undo entry
+        #[arg()]
+        id: i64
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PreviousAction {
+    /// List previous sessions
+    List {
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Print the windows and tabs of a previous session
+    Show {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Id of the previous session
+        #[arg()]
+        id: i64
+    },
+
+    /// Copy a previous session into the saved sessions
+    Promote {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Id of the previous session
+        #[arg()]
+        id: i64
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ThumbnailsAction {
+    /// Write thumbnails as image files named by session id
+    Extract {
+        /// Directory to write the images to
+        #[arg(short, long, value_name = "DIR")]
+        out: PathBuf,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Remove all thumbnails and VACUUM the database
+    Strip {
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SessionAction {
+    /// Rename a session
+    Rename {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Id of the session
+        #[arg()]
+        id: i64,
+
+        /// New name
+        #[arg()]
+        name: String
+    },
+
+    /// Append the windows of sessions to the first one and move the
+    /// others to the trash
+    Merge {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Ids of the sessions, the first one is kept
+        #[arg(required = true, num_args = 2..)]
+        ids: Vec<i64>,
+
+        /// Name of the merged session
+        #[arg(short, long)]
+        name: Option<String>
+    },
+
+    /// Make a session of each window of a session
+    Split {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Id of the session
+        #[arg()]
+        id: i64
+    },
+
+    /// Move a tab to the end of a window of the same or another
+    /// session. Positions start at 0, as printed by `sbh where`.
+    MoveTab {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Id of the session the tab is in
+        #[arg()]
+        from: i64,
+
+        /// Position of the window the tab is in
+        #[arg()]
+        window: usize,
+
+        /// Position of the tab in the window
+        #[arg()]
+        tab: usize,
+
+        /// Id of the session to move the tab to
+        #[arg()]
+        to: i64,
+
+        /// Position of the window to move the tab to, defaults to
+        /// the last one
+        #[arg(long)]
+        to_window: Option<usize>
+    },
+
+    /// Move sessions to the trash or delete them permanently
+    Delete {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Ids of the sessions
+        #[arg(required = true)]
+        ids: Vec<i64>,
+
+        /// Delete permanently instead of flagging as deleted
+        #[arg(long)]
+        purge: bool
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TagAction {
+    /// List all tags with the number of sessions having them
+    List {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        format: DataFormat,
+
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Tag sessions
+    Add {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Tag to add
+        #[arg()]
+        tag: String,
+
+        /// Ids of the sessions to tag
+        #[arg(required = true)]
+        ids: Vec<i64>
+    },
+
+    /// Remove a tag from sessions
+    Remove {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Tag to remove
+        #[arg()]
+        tag: String,
+
+        /// Ids of the sessions to remove the tag from
+        #[arg(required = true)]
+        ids: Vec<i64>
+    },
+
+    /// Rename a tag on all sessions
+    Rename {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Current name of the tag
+        #[arg()]
+        from: String,
+
+        /// New name of the tag
+        #[arg()]
+        to: String
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TrashAction {
+    /// List sessions flagged as deleted
+    List {
+        /// Path to database
+        #[arg()]
+        path: PathBuf
+    },
+
+    /// Clear the deleted flag of sessions
+    Restore {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Ids of the sessions to restore
+        #[arg(required = true)]
+        ids: Vec<i64>
+    },
+
+    /// Permanently delete sessions flagged as deleted
+    Purge {
+        /// Path to database
+        #[arg()]
+        path: PathBuf,
+
+        /// Ids of the sessions to purge. Purges all sessions flagged
+        /// as deleted if none are given.
+        #[arg()]
+        ids: Vec<i64>
+    }
+}
+
+/// Output format of reports
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum Format {
+    #[default]
+    Text,
+    Json
+}
+
+/// Output format of commands printing data, e.g. for dashboards
+/// and spreadsheets
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum DataFormat {
+    #[default]
+    Text,
+    Json,
+    Csv
+}
+
 #[derive(Subcommand, Debug)]
 pub enum ValidateAction {
     Database {
+        /// Output format of the report
+        #[arg(short, long, value_enum, default_value_t)]
+        format: Format,
+
         /// Path to database
         #[arg()]
         path: PathBuf
     },
 
     Backup {
-        /// Path to database
+        /// Output format of the report
+        #[arg(short, long, value_enum, default_value_t)]
+        format: Format,
+
+        /// Path to backup
         #[arg()]
         path: PathBuf
     }
 }
+
+impl Action {
+    /// Whether the action reads sessions and honours `--where`.
+    /// Actions on whole files or on sessions given by id don't.
+    pub fn supports_where(&self) -> bool {
+        match self {
+            Action::Dump { .. }
+            | Action::Stats { .. }
+            | Action::Backup { .. }
+            | Action::Autoname { .. }
+            | Action::Analyze { .. }
+            | Action::Timeline { .. }
+            | Action::Stale { .. }
+            | Action::Where { .. }
+            | Action::Find { .. }
+            | Action::Rewrite { .. }
+            | Action::RewriteUrls { .. } => true,
+            Action::Previous { action } => !matches!(action, PreviousAction::Promote { .. }),
+            Action::Thumbnails { action } => matches!(action, ThumbnailsAction::Extract { .. }),
+            Action::Tag { action } => matches!(action, TagAction::List { .. }),
+            Action::Trash { action } => matches!(action, TrashAction::List { .. }),
+            _ => false
+        }
+    }
+
+    /// The database or file an action operates on, if there is one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Action::Search { path } => path.as_deref(),
+            Action::Where { paths, .. } => paths.first().map(|p| p.as_path()),
+            Action::Backup { path, .. }
+            | Action::Import { path, .. }
+            | Action::New { path }
+            | Action::Stats { path, .. }
+            | Action::Dump { path }
+            | Action::Du { path, .. }
+            | Action::Autoname { path, .. }
+            | Action::Rewrite { path, .. }
+            | Action::RewriteUrls { path, .. }
+            | Action::Timeline { path, .. }
+            | Action::Stale { path, .. }
+            | Action::Forget { path, .. }
+            | Action::Id { path }
+            | Action::Repair { path, .. } => Some(path),
+            Action::Find { path, .. } => Some(path),
+            Action::Index { action } => match action {
+                IndexAction::Build { path, .. } => Some(path)
+            },
+            Action::Analyze { action } => match action {
+                AnalyzeAction::Domains { path, .. } => Some(path)
+            },
+            Action::Undo { action } => match action {
+                UndoAction::List { path } | UndoAction::Restore { path, .. } => Some(path)
+            },
+            Action::Previous { action } => match action {
+                PreviousAction::List { path }
+                | PreviousAction::Show { path, .. }
+                | PreviousAction::Promote { path, .. } => Some(path)
+            },
+            Action::Thumbnails { action } => match action {
+                ThumbnailsAction::Extract { path, .. } | ThumbnailsAction::Strip { path } => {
+                    Some(path)
+                }
+            },
+            Action::Session { action } => match action {
+                SessionAction::Rename { path, .. }
+                | SessionAction::Merge { path, .. }
+                | SessionAction::Split { path, .. }
+                | SessionAction::MoveTab { path, .. }
+                | SessionAction::Delete { path, .. } => Some(path)
+            },
+            Action::Tag { action } => match action {
+                TagAction::List { path, .. }
+                | TagAction::Add { path, .. }
+                | TagAction::Remove { path, .. }
+                | TagAction::Rename { path, .. } => Some(path)
+            },
+            Action::Trash { action } => match action {
+                TrashAction::List { path }
+                | TrashAction::Restore { path, .. }
+                | TrashAction::Purge { path, .. } => Some(path)
+            },
+            Action::Validate { action } => match action {
+                ValidateAction::Database { path, .. } | ValidateAction::Backup { path, .. } => {
+                    Some(path)
+                }
+            }
+        }
+    }
+}
@@ -25,8 +25,6 @@ pub fn get_path() -> Option<PathBuf> {
         }
         whoami::Platform::MacOS => dirs::data_local_dir(),
         whoami::Platform::Windows => dirs::data_local_dir(),
-        _ => {
-            todo!("Operating system not supported");
-        }
+        _ => None
     }
 }
@@ -0,0 +1,75 @@
+//! Optional configuration file. It is JSON and read from the path in
+//! `SBH_CONFIG` or `sbh/config.json` in the config directory of the
+//! user, e.g.
+//!
+//! ```json
+//! {
+//!   "normalize": {
+//!     "tracking": { "strip_params": ["ref", "source"] },
+//!     "canonical": { "strip_www": false, "keep_params": ["utm_*"] }
+//!   }
+//! }
+//! ```
+
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::session_buddy::normalize::{RuleChanges, Rules};
+use crate::Error;
+
+/// Environment variable with the path of the config file.
+pub const SBH_CONFIG: &str = "SBH_CONFIG";
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub normalize: NormalizeConfig
+}
+
+/// Changes to the built-in URL normalization rule sets.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NormalizeConfig {
+    /// Rules removing tracking parameters, used to rewrite URLs.
+    pub tracking: RuleChanges,
+    /// Rules telling whether two URLs are the same page, used to
+    /// find duplicates and match URLs.
+    pub canonical: RuleChanges
+}
+
+impl Config {
+    /// Path of the config file, if there is a config directory.
+    pub fn path() -> Option<PathBuf> {
+        std::env::var_os(SBH_CONFIG)
+            .map(PathBuf::from)
+            .or_else(|| dirs::config_dir().map(|d| d.join("sbh").join("config.json")))
+    }
+
+    /// Read the config file. Without one, everything is at its
+    /// default, unless `SBH_CONFIG` names a file that doesn't exist.
+    pub fn load() -> Result<Config, Error> {
+        let Some(path) = Config::path() else {
+            return Ok(Config::default());
+        };
+
+        if !path.exists() {
+            return match std::env::var_os(SBH_CONFIG) {
+                Some(_) => Err(Error::Config(format!("{} doesn't exist", path.display()))),
+                None => Ok(Config::default())
+            };
+        }
+
+        serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn tracking_rules(&self) -> Rules {
+        Rules::tracking().extend(&self.normalize.tracking)
+    }
+
+    pub fn canonical_rules(&self) -> Rules {
+        Rules::canonical().extend(&self.normalize.canonical)
+    }
+}
@@ -0,0 +1,195 @@
+use serde::Serialize;
+
+/// Errors returned by the library. Nothing in here should ever make
+/// the library panic, so callers can embed it without risking to
+/// abort the process.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Any error reported by SQLite or the database driver.
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] sqlx::Error),
+
+    /// (De)serializing JSON failed.
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// The database doesn't look like a Session Buddy database,
+    /// e.g. a table or a required setting is missing.
+    #[error("Schema error: {0}")]
+    Schema(String),
+
+    /// A backup or a stored value is not in the expected format.
+    #[error("Format error: {0}")]
+    Format(String),
+
+    /// A session or other entry asked for doesn't exist.
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    /// Searching the filesystem for databases failed.
+    #[error("Search error: {0}")]
+    Search(#[from] globwalk::GlobError),
+
+    /// A URL pattern, regular expression or expression given by the
+    /// user doesn't parse.
+    #[error("Invalid pattern: {0}")]
+    Pattern(String),
+
+    /// Reading or writing CSV failed.
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+
+    /// The config file is missing or doesn't parse.
+    #[error("Config error: {0}")]
+    Config(String),
+
+    /// The database is in use, e.g. the browser has it open, or left
+    /// a journal behind.
+    #[error("Database is locked: {0}")]
+    Locked(String),
+
+    /// The database changed between previewing and applying changes.
+    #[error("Conflict: {0}")]
+    Conflict(String)
+}
+
+impl Error {
+    /// Classify the error, e.g. to tell a locked database from a
+    /// corrupt one.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Io(e) => io_kind(e),
+            Error::Sqlite(sqlx::Error::Io(e)) => io_kind(e),
+            Error::Sqlite(sqlx::Error::Database(e)) => {
+                // Extended result codes carry the primary result code in
+                // the lower 8 bits.
+                let code = e.code().and_then(|c| c.parse::<i32>().ok()).unwrap_or(0) & 0xff;
+                match code {
+                    SQLITE_ERROR if e.message().starts_with("no such table") => ErrorKind::Schema,
+                    SQLITE_BUSY | SQLITE_LOCKED => ErrorKind::Locked,
+                    SQLITE_CORRUPT | SQLITE_NOTADB => ErrorKind::CorruptDatabase,
+                    SQLITE_PERM | SQLITE_READONLY => ErrorKind::PermissionDenied,
+                    SQLITE_CANTOPEN => ErrorKind::NotFound,
+                    _ => ErrorKind::Sqlite
+                }
+            }
+            Error::Sqlite(sqlx::Error::ColumnDecode { .. }) => ErrorKind::CorruptDatabase,
+            Error::Sqlite(_) => ErrorKind::Sqlite,
+            Error::Json(_) => ErrorKind::InvalidJson,
+            Error::Schema(_) => ErrorKind::Schema,
+            Error::Format(_) => ErrorKind::InvalidFormat,
+            Error::NotFound(_) => ErrorKind::NotFound,
+            Error::Search(_) => ErrorKind::Search,
+            Error::Pattern(_) => ErrorKind::InvalidPattern,
+            Error::Config(_) => ErrorKind::InvalidConfig,
+            Error::Locked(_) => ErrorKind::Locked,
+            Error::Conflict(_) => ErrorKind::Conflict,
+            Error::Csv(e) => match e.kind() {
+                csv::ErrorKind::Io(e) => io_kind(e),
+                _ => ErrorKind::InvalidFormat
+            }
+        }
+    }
+
+    /// Something a user could try to get rid of the error.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            // Hints about paths don't help with missing entries.
+            Error::NotFound(_) => None,
+            _ => self.kind().hint()
+        }
+    }
+}
+
+fn io_kind(e: &std::io::Error) -> ErrorKind {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => ErrorKind::NotFound,
+        std::io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+        _ => ErrorKind::Io
+    }
+}
+
+// Primary SQLite result codes, see https://www.sqlite.org/rescode.html
+const SQLITE_ERROR: i32 = 1;
+const SQLITE_PERM: i32 = 3;
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+const SQLITE_READONLY: i32 = 8;
+const SQLITE_CORRUPT: i32 = 11;
+const SQLITE_CANTOPEN: i32 = 14;
+const SQLITE_NOTADB: i32 = 26;
+
+/// Class of an Error. Every class has its own exit code, so scripts
+/// can react to failures without parsing log output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Any other IO error.
+    Io,
+    /// A file, database or entry doesn't exist or can't be opened.
+    NotFound,
+    /// Not allowed to read or write a file or database.
+    PermissionDenied,
+    /// The database is locked, usually by the browser.
+    Locked,
+    /// The database file is damaged or not a database at all.
+    CorruptDatabase,
+    /// Any other SQLite error.
+    Sqlite,
+    /// A backup is not valid JSON.
+    InvalidJson,
+    /// A backup or stored value doesn't have the expected structure.
+    InvalidFormat,
+    /// Not a Session Buddy database.
+    Schema,
+    /// Searching for databases failed.
+    Search,
+    /// A pattern or expression given by the user doesn't parse.
+    InvalidPattern,
+    /// The config file is missing or doesn't parse.
+    InvalidConfig,
+    /// The database changed since changes to it were previewed.
+    Conflict
+}
+
+impl ErrorKind {
+    /// Exit codes start at 10 to stay clear of the codes used for
+    /// validation reports and by clap for usage errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Io => 10,
+            ErrorKind::NotFound => 11,
+            ErrorKind::PermissionDenied => 12,
+            ErrorKind::Locked => 13,
+            ErrorKind::CorruptDatabase => 14,
+            ErrorKind::Sqlite => 15,
+            ErrorKind::InvalidJson => 16,
+            ErrorKind::InvalidFormat => 17,
+            ErrorKind::Schema => 18,
+            ErrorKind::Search => 19,
+            ErrorKind::InvalidPattern => 20,
+            ErrorKind::InvalidConfig => 21,
+            ErrorKind::Conflict => 22
+        }
+    }
+
+    /// Something a user could try to get rid of the error.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            ErrorKind::NotFound => Some("Check the path or use `sbh search` to find databases"),
+            ErrorKind::PermissionDenied => Some("Check the permissions of the file"),
+            ErrorKind::Locked => Some("Close the browser or work on a copy of the database"),
+            ErrorKind::CorruptDatabase => Some("Run `sbh validate database` for details"),
+            ErrorKind::InvalidJson | ErrorKind::InvalidFormat => {
+                Some("Run `sbh validate backup` for details")
+            }
+            ErrorKind::Schema => Some("Make sure this is a Session Buddy database"),
+            ErrorKind::Conflict => Some("Run the command again to preview the current changes"),
+            _ => None
+        }
+    }
+}
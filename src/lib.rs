@@ -1,7 +1,11 @@
 pub mod args;
 pub mod chrome;
+pub mod config;
+pub mod error;
 pub mod session_buddy;
 
+pub use error::{Error, ErrorKind};
+
 //const BOM: &str = "\u{FEFF}";
 const SBH_MAX_WALK_DEPTH: usize = 8;
 
@@ -28,7 +32,7 @@ pub mod util {
     pub fn ts() -> Duration {
         SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
+            .unwrap_or_default()
     }
 
     pub fn get_user_agent() -> String {
@@ -53,6 +57,22 @@ pub mod util {
         whoami::lang().next().unwrap_or("en-US".to_string())
     }
 
+    /// Format a number of bytes for humans, e.g. `4.2 MiB`.
+    pub fn format_bytes(bytes: u64) -> String {
+        const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut size = bytes as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            format!("{} {}", bytes, UNITS[0])
+        } else {
+            format!("{:.1} {}", size, UNITS[unit])
+        }
+    }
+
     pub fn generate_gid() -> String {
         thread_rng()
             .sample_iter(&Alphanumeric)
@@ -104,7 +124,7 @@ mod tests {
 
     use regex::Regex;
 
-    use crate::util::get_mtime_fmt;
+    use crate::util::{format_bytes, get_mtime_fmt};
 
     const TS_REGEX: &str = r"^[0-9]{4}_[0-9]{2}_[0-9]{2}_[0-9]{2}_[0-9]{2}_[0-9]{2}$";
 
@@ -122,4 +142,11 @@ mod tests {
         println!("{:#?}", ts);
         assert!(re.is_match(&ts));
     }
+
+    #[test]
+    fn format_bytes_works() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(400 * 1024 * 1024), "400.0 MiB");
+    }
 }
@@ -1,13 +1,31 @@
+use std::path::Path;
+
+use chrono::{Duration, Utc};
 use clap::Parser;
+use dialoguer::{Confirm, Input};
 use env_logger::Env;
-use serde::ser::StdError;
 
-use log::{error, info};
-use sbh::args::{Action, Args, ValidateAction};
-use sbh::session_buddy::{backup, database};
+use log::{error, info, warn};
+use sbh::args::{
+    Action, AnalyzeAction, Args, DataFormat, Format, IndexAction, PreviousAction, SessionAction,
+    TagAction, ThumbnailsAction, TrashAction, UndoAction, ValidateAction
+};
+use sbh::config::Config;
+use sbh::session_buddy::backup::Selection;
+use sbh::session_buddy::filter::Filter;
+use sbh::session_buddy::normalize::Rules;
+use sbh::session_buddy::redact::Redactor;
+use sbh::session_buddy::report::Report;
+use sbh::session_buddy::rewrite::Change;
+use sbh::session_buddy::{
+    analyze, autoname, backup, database, edit, forget, index, lookup, previous, repair, rewrite,
+    stale, tag, thumbnail, timeline, trash, undo, usage
+};
+use sbh::util::format_bytes;
+use sbh::Error;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn StdError>> {
+async fn main() {
     let env = Env::default().default_filter_or("info");
 
     env_logger::Builder::from_env(env)
@@ -17,124 +35,788 @@ async fn main() -> Result<(), Box<dyn StdError>> {
         .format_timestamp(None)
         .init();
 
-    // TODO This could be cleaned up a little bit more
-
     let args = Args::parse();
-    match args.action {
-        Action::Dump { path } => {
-            database::dump(&path).await.unwrap_or_else(|e| {
-                error!("{:?}", e);
-                std::process::exit(1)
-            });
-        }
+    let path = args.action.path().map(Path::to_path_buf);
+    let result = match args.filter() {
+        Ok(filter) => run(args.action, &filter).await,
+        Err(e) => Err(e)
+    };
 
-        Action::Stats { path } => {
-            database::stats(&path).await.unwrap_or_else(|e| {
-                error!("{:?}", e);
-                std::process::exit(1)
-            });
+    if let Err(e) = result {
+        report_error(&e, path.as_deref(), args.error_format);
+        std::process::exit(e.kind().exit_code())
+    }
+}
+
+async fn run(action: Action, filter: &Filter) -> Result<(), Error> {
+    match action {
+        Action::Dump { path } => database::dump(&path, filter).await?,
+
+        Action::Stats { path, format } => {
+            let rules = Config::load()?.canonical_rules();
+            let stats = database::stats(&path, filter, &rules).await?;
+            match format {
+                DataFormat::Text => println!("{}", stats),
+                DataFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+                DataFormat::Csv => {
+                    let (names, values): (Vec<_>, Vec<_>) = stats.fields().into_iter().unzip();
+                    let mut writer = csv::Writer::from_writer(std::io::stdout());
+                    writer.write_record(names)?;
+                    writer.write_record(values)?;
+                    writer.flush()?;
+                }
+            }
         }
 
-        Action::Id { path } => match database::id(&path).await {
-            Ok(id) => println!("{}", id),
-            Err(e) => {
-                error!("{:?}", e);
-                std::process::exit(1)
+        Action::Du {
+            path,
+            top,
+            compact,
+            format
+        } => {
+            let usage = usage::usage(&path, top).await?;
+            match format {
+                Format::Text => println!("{}", usage),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&usage)?)
             }
-        },
+            if compact {
+                let (before, after) = usage::compact(&path).await?;
+                info!(
+                    "Compacted {} to {}",
+                    format_bytes(before),
+                    format_bytes(after)
+                );
+            }
+        }
+
+        Action::Id { path } => println!("{}", database::id(&path).await?),
+
+        Action::Backup {
+            path,
+            out,
+            ids,
+            name,
+            tags,
+            since,
+            until,
+            redact,
+            keep_domains,
+            redact_key
+        } => {
+            let selection = Selection {
+                ids,
+                name,
+                tags,
+                since,
+                until
+            };
+            let redactor = if redact {
+                Some(Redactor::new(redact_key.as_deref(), keep_domains)?)
+            } else {
+                None
+            };
 
-        Action::Backup { path, out } => {
             // TODO the search option operates on multiple databases, b/c
             // multiple may be found via searching. This is
             // inconsistent with other behaviour. Make a decision.
 
-            // TODO Remove the clones().
-
             //if search {
             //    let paths = database::search(None).await?;
 
             //    for path in paths {
-            //        database::backup(&path, out.clone())
-            //            .await
-            //            .unwrap_or_else(|e| {
-            //                error!("{:?}", e);
-            //                std::process::exit(1)
-            //            });
+            //        database::backup(&path, out.clone(), filter).await?;
             //    }
             //} else {
-            database::backup(&path, out.clone())
-                .await
-                .unwrap_or_else(|e| {
-                    error!("{:?}", e);
-                    std::process::exit(1)
-                });
+            database::backup(&path, out, filter, &selection, redactor.as_ref()).await?;
             //}
         }
 
         Action::New { path } => {
-            database::create(&path).await.unwrap_or_else(|e| {
-                error!("{:?}", e);
-                std::process::exit(1)
-            });
+            database::create(&path).await?;
         }
 
         Action::Validate { action } => match action {
-            ValidateAction::Database { path } => match database::validate(&path).await {
-                Ok(_) => {
-                    info!("Database valid");
+            ValidateAction::Database { path, format } => {
+                print_report(&database::validate(&path).await?, format)?;
+                info!("Database valid");
+            }
+            ValidateAction::Backup { path, format } => {
+                print_report(&backup::validate(&path).await?, format)?;
+                info!("Backup valid");
+            }
+        },
+
+        Action::Repair {
+            path,
+            dry_run,
+            format
+        } => {
+            print_report(&repair::repair(&path, dry_run).await?, format)?;
+            if dry_run {
+                info!("Dry run, nothing written");
+            }
+        }
+
+        Action::Autoname { yes, dry_run, path } => {
+            let mut names = vec![];
+
+            for suggestion in autoname::suggestions(&path, filter).await? {
+                if yes || dry_run {
+                    println!("{:>5}  {}", suggestion.id, suggestion.name);
+                    names.push((suggestion.id, suggestion.name));
+                    continue;
+                }
+
+                let name: String = Input::new()
+                    .with_prompt(format!(
+                        "Session {} from {} with {} tabs, empty to skip",
+                        suggestion.id,
+                        suggestion.created.format("%Y-%m-%d %H:%M"),
+                        suggestion.tabs
+                    ))
+                    .with_initial_text(suggestion.name)
+                    .allow_empty(true)
+                    .interact_text()?;
+                if !name.trim().is_empty() {
+                    names.push((suggestion.id, name.trim().to_string()));
+                }
+            }
+
+            if !dry_run {
+                let named = autoname::apply(&path, &names).await?;
+                info!("Named {} sessions", named);
+            }
+        }
+
+        Action::Analyze { action } => match action {
+            AnalyzeAction::Domains {
+                group_by,
+                depth,
+                scheme,
+                pattern,
+                min_count,
+                sort,
+                reverse,
+                limit,
+                format,
+                path
+            } => {
+                let options = analyze::Options {
+                    group_by,
+                    depth,
+                    schemes: scheme,
+                    pattern,
+                    min_count,
+                    sort_by: sort,
+                    reverse,
+                    limit
+                };
+                let groups = analyze::domains(&path, filter, &options).await?;
+                match format {
+                    DataFormat::Text => {
+                        for group in groups.iter() {
+                            println!(
+                                "{:>7} tabs {:>5} sessions  {}  {}  {}",
+                                group.tabs,
+                                group.sessions,
+                                group.first_seen.format("%Y-%m-%d"),
+                                group.last_seen.format("%Y-%m-%d"),
+                                group.key
+                            );
+                        }
+                    }
+                    DataFormat::Json => println!("{}", serde_json::to_string_pretty(&groups)?),
+                    DataFormat::Csv => {
+                        let mut writer = csv::Writer::from_writer(std::io::stdout());
+                        for group in groups.iter() {
+                            writer.serialize(group)?;
+                        }
+                        writer.flush()?;
+                    }
                 }
-                Err(e) => {
-                    error!("{:?}", e);
-                    std::process::exit(1)
+            }
+        },
+
+        Action::Timeline {
+            period,
+            by,
+            format,
+            path
+        } => {
+            let buckets = timeline::timeline(&path, filter, period, by).await?;
+            match format {
+                DataFormat::Text => {
+                    let tabs: Vec<u64> = buckets.iter().map(|b| b.tabs).collect();
+                    let sessions: Vec<u64> = buckets.iter().map(|b| b.sessions).collect();
+                    println!("Tabs:     {}", timeline::sparkline(&tabs));
+                    println!("Sessions: {}", timeline::sparkline(&sessions));
+                    println!();
+                    println!(
+                        "{:<10} {:>8} {:>8} {:>8} {:>8}",
+                        "Period", "Sessions", "Tabs", "Domains", "Previous"
+                    );
+                    for bucket in buckets.iter() {
+                        println!(
+                            "{:<10} {:>8} {:>8} {:>8} {:>8}",
+                            bucket.period,
+                            bucket.sessions,
+                            bucket.tabs,
+                            bucket.domains,
+                            bucket.previous_sessions
+                        );
+                    }
                 }
-            },
-            ValidateAction::Backup { path } => match backup::validate(&path).await {
-                Ok(_) => {
-                    info!("Backup valid");
+                DataFormat::Json => println!("{}", serde_json::to_string_pretty(&buckets)?),
+                DataFormat::Csv => {
+                    let mut writer = csv::Writer::from_writer(std::io::stdout());
+                    for bucket in buckets.iter() {
+                        writer.serialize(bucket)?;
+                    }
+                    writer.flush()?;
                 }
-                Err(e) => {
-                    error!("{:?}", e);
-                    std::process::exit(1)
+            }
+        }
+
+        Action::Stale {
+            days,
+            sort,
+            format,
+            prune,
+            yes,
+            path
+        } => {
+            let cutoff = Utc::now() - Duration::days(days);
+            let urls = stale::stale(&path, filter, cutoff, sort).await?;
+            match format {
+                DataFormat::Text => {
+                    for url in urls.iter() {
+                        println!(
+                            "{:>5} days  {:<24} {}  ({})",
+                            (Utc::now() - url.last_seen).num_days(),
+                            url.domain,
+                            url.url,
+                            url.sessions.iter().cloned().collect::<Vec<_>>().join(", ")
+                        );
+                    }
+                }
+                DataFormat::Json => println!("{}", serde_json::to_string_pretty(&urls)?),
+                DataFormat::Csv => {
+                    let mut writer = csv::Writer::from_writer(std::io::stdout());
+                    writer.write_record(["url", "domain", "last_seen", "sessions"])?;
+                    for url in urls.iter() {
+                        writer.write_record([
+                            url.url.clone(),
+                            url.domain.clone(),
+                            url.last_seen.to_rfc3339(),
+                            url.sessions.iter().cloned().collect::<Vec<_>>().join("; ")
+                        ])?;
+                    }
+                    writer.flush()?;
                 }
             }
+
+            if prune && !urls.is_empty() {
+                let confirmed = yes
+                    || Confirm::new()
+                        .with_prompt(format!(
+                            "Remove {} stale URLs from {}?",
+                            urls.len(),
+                            path.display()
+                        ))
+                        .interact()?;
+                if confirmed {
+                    let urls = urls.into_iter().map(|u| u.url).collect();
+                    let report = stale::prune(&path, filter, &urls).await?;
+                    info!(
+                        "Removed {} tabs, changed {} sessions and moved {} emptied sessions to the trash",
+                        report.tabs, report.sessions, report.deleted_sessions
+                    );
+                }
+            }
+        }
+
+        Action::Forget {
+            site,
+            yes,
+            dry_run,
+            path
+        } => {
+            let site = forget::Site::new(&site)?;
+            let report = forget::forget(&path, &site, true).await?;
+            print_forget_report(&report);
+
+            let found = report.tabs + report.undo_tabs > 0;
+            if !dry_run && found {
+                let confirmed = yes
+                    || Confirm::new()
+                        .with_prompt(format!(
+                            "Remove {} tabs from {} for good?",
+                            report.tabs + report.undo_tabs,
+                            path.display()
+                        ))
+                        .interact()?;
+                if confirmed {
+                    let report = forget::forget(&path, &site, false).await?;
+                    println!("Size before:    {:>10}", format_bytes(report.size_before));
+                    println!("Size after:     {:>10}", format_bytes(report.size_after));
+                    if report.index_removed {
+                        info!("Removed the search index, run `sbh index build` to recreate it");
+                    }
+                }
+            }
+        }
+
+        Action::Where {
+            pattern,
+            mode,
+            raw,
+            format,
+            paths
+        } => {
+            let mut matcher = lookup::Matcher::new(mode, &pattern)?;
+            if !raw {
+                matcher = matcher.normalize(&Config::load()?.canonical_rules());
+            }
+            let sources = match paths.is_empty() {
+                true => database::search(None).await?,
+                false => paths
+            };
+
+            let mut hits = vec![];
+            for source in sources.iter() {
+                match lookup::lookup(source, &matcher, filter).await {
+                    Ok(h) => hits.extend(h),
+                    // A single broken database shouldn't spoil a search
+                    // across many.
+                    Err(e) if sources.len() > 1 => warn!("{}: {}", source.display(), e),
+                    Err(e) => return Err(e)
+                }
+            }
+
+            match format {
+                DataFormat::Text => {
+                    for hit in hits.iter() {
+                        println!(
+                            "{}  {} {:>5}  {}  window {} tab {}  {}  {}  {}",
+                            hit.source.display(),
+                            hit.kind,
+                            hit.session_id.map(|id| id.to_string()).unwrap_or_default(),
+                            hit.created.format("%Y-%m-%d %H:%M"),
+                            hit.window,
+                            hit.tab,
+                            hit.session_name,
+                            hit.title.as_deref().unwrap_or_default(),
+                            hit.url
+                        );
+                    }
+                }
+                DataFormat::Json => println!("{}", serde_json::to_string_pretty(&hits)?),
+                DataFormat::Csv => {
+                    let mut writer = csv::Writer::from_writer(std::io::stdout());
+                    for hit in hits.iter() {
+                        writer.serialize(hit)?;
+                    }
+                    writer.flush()?;
+                }
+            }
+        }
+
+        Action::Index { action } => match action {
+            IndexAction::Build {
+                rebuild,
+                index,
+                path
+            } => {
+                let index = index.unwrap_or_else(|| index::default_path(&path));
+                let report = index::build(&path, &index, rebuild).await?;
+                info!(
+                    "Indexed {}: {} added, {} updated, {} removed, {} unchanged sessions",
+                    index.display(),
+                    report.added,
+                    report.updated,
+                    report.removed,
+                    report.unchanged
+                );
+            }
         },
 
-        Action::Search { path } => {
-            let t = sbh::util::ts();
-            match database::search(path).await {
-                Ok(dbs) => {
-                    info!("Search took {:?}", sbh::util::ts() - t);
-                    info!("Databases found: {}", dbs.len());
-                    for db in dbs.iter() {
-                        println!("{}", db.display());
+        Action::Find {
+            query,
+            limit,
+            index,
+            format,
+            path
+        } => {
+            let index = index.unwrap_or_else(|| index::default_path(&path));
+            let found = index::find(&path, &index, &query, limit, filter).await?;
+            match format {
+                DataFormat::Text => {
+                    for tab in found.iter() {
+                        println!(
+                            "{} {:>5}  window {} tab {}  {}\n    {}\n    {}",
+                            tab.kind,
+                            tab.session_id,
+                            tab.window,
+                            tab.tab,
+                            tab.session_name,
+                            tab.url,
+                            tab.snippet
+                        );
                     }
                 }
-                Err(e) => {
-                    error!("{:?}", e);
-                    std::process::exit(1)
+                DataFormat::Json => println!("{}", serde_json::to_string_pretty(&found)?),
+                DataFormat::Csv => {
+                    let mut writer = csv::Writer::from_writer(std::io::stdout());
+                    for tab in found.iter() {
+                        writer.serialize(tab)?;
+                    }
+                    writer.flush()?;
                 }
             }
         }
 
-        Action::Import { path, files } => match database::collect_saved_sessions(&files).await {
-            Ok(sessions) => {
-                let mut conn = database::connect(&path).await?;
-                match database::import(&mut conn, &sessions).await {
-                    Ok(()) => {
-                        info!("Imported {} sessions", sessions.len());
+        Action::Undo { action } => match action {
+            UndoAction::List { path } => {
+                for entry in undo::undo_entries(&path).await? {
+                    println!(
+                        "{:>5}  {}  {:<16} {}",
+                        entry.id,
+                        entry
+                            .creation_date_time
+                            .map(|t| t.to_string())
+                            .unwrap_or_else(|| "-".to_string()),
+                        entry.action.unwrap_or_default(),
+                        entry.description.unwrap_or_default()
+                    );
+                }
+            }
+            UndoAction::Restore { id, path } => {
+                let session = undo::restore(&path, id).await?;
+                info!("Restored undo entry {} as session {}", id, session);
+            }
+        },
+
+        Action::Previous { action } => match action {
+            PreviousAction::List { path } => {
+                for session in database::previous_sessions(&path)
+                    .await?
+                    .iter()
+                    .filter(|s| filter.matches_previous(s))
+                {
+                    let saved = session.to_saved_session();
+                    println!(
+                        "{:>5}  {}  {:>3} windows {:>5} tabs",
+                        session.id,
+                        session.recording_date_time,
+                        saved.count_windows(),
+                        saved.count_tabs()
+                    );
+                }
+            }
+            PreviousAction::Show { path, id } => {
+                let session = previous::previous_session(&path, id).await?;
+                if !filter.matches_deleted(session.is_deleted()) {
+                    return Err(Error::NotFound(format!(
+                        "previous session {} is flagged as deleted, see --include-deleted",
+                        id
+                    )));
+                }
+                println!("Id:       {}", session.id);
+                println!("Recorded: {}", session.recording_date_time);
+                println!("Created:  {}", session.creation_date_time);
+                // Tabs are matched like those of the saved session the
+                // snapshot would be promoted to.
+                let saved = session.to_saved_session();
+                for (i, window) in saved.windows.iter().enumerate() {
+                    println!();
+                    println!("Window {}", i + 1);
+                    for tab in window
+                        .tabs
+                        .iter()
+                        .flatten()
+                        .filter(|t| filter.matches_tab(&saved, window, t))
+                    {
+                        println!(
+                            "  {}  {}",
+                            tab.url.as_deref().unwrap_or_default(),
+                            tab.title.as_deref().unwrap_or_default()
+                        );
                     }
-                    Err(e) => {
-                        error!("{:?}", e);
-                        std::process::exit(1)
+                }
+            }
+            PreviousAction::Promote { path, id } => {
+                let session = previous::promote(&path, id).await?;
+                info!("Promoted previous session {} to session {}", id, session);
+            }
+        },
+
+        Action::Thumbnails { action } => match action {
+            ThumbnailsAction::Extract { out, path } => {
+                let report = thumbnail::extract(&path, &out, filter).await?;
+                info!(
+                    "Extracted {} thumbnails to {}",
+                    report.files.len(),
+                    out.display()
+                );
+                if !report.failures.is_empty() {
+                    warn!(
+                        "Skipped {} thumbnails that could not be decoded",
+                        report.failures.len()
+                    );
+                }
+            }
+            ThumbnailsAction::Strip { path } => {
+                let report = thumbnail::strip(&path).await?;
+                println!(
+                    "Thumbnails:  {:>10} ({})",
+                    report.thumbnails,
+                    format_bytes(report.thumbnail_bytes)
+                );
+                println!("Size before: {:>10}", format_bytes(report.size_before));
+                println!("Size after:  {:>10}", format_bytes(report.size_after));
+            }
+        },
+
+        Action::Rewrite {
+            from,
+            to,
+            title_from,
+            title_to,
+            yes,
+            dry_run,
+            path
+        } => {
+            let url = rewrite::Replace { from, to };
+            let title = title_from
+                .zip(title_to)
+                .map(|(from, to)| rewrite::Replace { from, to });
+
+            let changes = rewrite::rewrite(&path, filter, &url, title.as_ref(), true, None).await?;
+            print_changes(&changes);
+
+            if !dry_run && !changes.is_empty() {
+                let confirmed = yes
+                    || Confirm::new()
+                        .with_prompt(format!(
+                            "Apply {} changes to {} sessions?",
+                            changes.len(),
+                            Change::sessions(&changes)
+                        ))
+                        .interact()?;
+                if confirmed {
+                    let changes = rewrite::rewrite(
+                        &path,
+                        filter,
+                        &url,
+                        title.as_ref(),
+                        false,
+                        Some(&changes)
+                    )
+                    .await?;
+                    info!(
+                        "Rewrote {} fields in {} sessions",
+                        changes.len(),
+                        Change::sessions(&changes)
+                    );
+                }
+            }
+        }
+
+        Action::RewriteUrls {
+            strip_tracking,
+            dry_run,
+            path
+        } => {
+            // The only rule set so far, clap insists on it.
+            let rules = match strip_tracking {
+                true => Config::load()?.tracking_rules(),
+                false => Rules::default()
+            };
+            let changes = rewrite::rewrite_urls(&path, filter, &rules, dry_run).await?;
+            if dry_run {
+                print_changes(&changes);
+            } else {
+                info!(
+                    "Rewrote {} URLs in {} sessions",
+                    changes.len(),
+                    Change::sessions(&changes)
+                );
+            }
+        }
+
+        Action::Session { action } => match action {
+            SessionAction::Rename { path, id, name } => {
+                edit::rename(&path, id, &name).await?;
+                info!("Renamed session {}", id);
+            }
+            SessionAction::Merge { path, ids, name } => {
+                let merged = edit::merge(&path, &ids, name.as_deref()).await?;
+                info!("Merged {} sessions into session {}", merged, ids[0]);
+            }
+            SessionAction::Split { path, id } => {
+                let ids = edit::split(&path, id).await?;
+                if ids.is_empty() {
+                    info!("Session {} has less than two windows", id);
+                }
+                for new in ids.iter() {
+                    println!("{}", new);
+                }
+            }
+            SessionAction::MoveTab {
+                path,
+                from,
+                window,
+                tab,
+                to,
+                to_window
+            } => {
+                edit::move_tab(&path, from, window, tab, to, to_window).await?;
+                info!("Moved tab to session {}", to);
+            }
+            SessionAction::Delete { path, ids, purge } => {
+                edit::delete(&path, &ids, purge).await?;
+                match purge {
+                    true => info!("Deleted {} sessions", ids.len()),
+                    false => info!("Moved {} sessions to the trash", ids.len())
+                }
+            }
+        },
+
+        Action::Tag { action } => match action {
+            TagAction::List { format, path } => {
+                let tags = tag::list(&path, filter).await?;
+                match format {
+                    DataFormat::Text => {
+                        for t in tags.iter() {
+                            println!("{:>6}  {}", t.sessions, t.tag);
+                        }
                     }
-                };
+                    DataFormat::Json => println!("{}", serde_json::to_string_pretty(&tags)?),
+                    DataFormat::Csv => {
+                        let mut writer = csv::Writer::from_writer(std::io::stdout());
+                        for t in tags.iter() {
+                            writer.serialize(t)?;
+                        }
+                        writer.flush()?;
+                    }
+                }
+            }
+            TagAction::Add { path, tag, ids } => {
+                let changed = tag::add(&path, &ids, &tag).await?;
+                info!("Tagged {} sessions", changed);
+            }
+            TagAction::Remove { path, tag, ids } => {
+                let changed = tag::remove(&path, &ids, &tag).await?;
+                info!("Removed the tag from {} sessions", changed);
+            }
+            TagAction::Rename { path, from, to } => {
+                let changed = tag::rename(&path, &from, &to).await?;
+                info!("Renamed the tag on {} sessions", changed);
+            }
+        },
+
+        Action::Trash { action } => match action {
+            TrashAction::List { path } => {
+                for session in trash::deleted_sessions(&path)
+                    .await?
+                    .iter()
+                    .filter(|s| filter.matches_expr(s))
+                {
+                    println!(
+                        "{:>5}  {}  {:>3} windows {:>5} tabs  {}",
+                        session.id.unwrap_or_default(),
+                        session.modification_date_time,
+                        session.count_windows(),
+                        session.count_tabs(),
+                        session.name
+                    );
+                }
+            }
+            TrashAction::Restore { path, ids } => {
+                trash::restore(&path, &ids).await?;
+                info!("Restored {} sessions", ids.len());
             }
-            Err(e) => {
-                error!("{:?}", e);
-                std::process::exit(1)
+            TrashAction::Purge { path, ids } => {
+                let purged = trash::purge(&path, &ids).await?;
+                info!("Purged {} sessions", purged);
             }
+        },
+
+        Action::Search { path } => {
+            let t = sbh::util::ts();
+            let dbs = database::search(path).await?;
+            info!("Search took {:?}", sbh::util::ts() - t);
+            info!("Databases found: {}", dbs.len());
+            for db in dbs.iter() {
+                println!("{}", db.display());
+            }
+        }
+
+        Action::Import { path, files } => {
+            let sessions = database::collect_saved_sessions(&files).await?;
+            let mut conn = database::connect(&path).await?;
+            database::import(&mut conn, &sessions).await?;
+            info!("Imported {} sessions", sessions.len());
         }
     }
 
     Ok(())
 }
+
+/// Print a report and exit with its exit code if there is anything
+/// worse than info in it.
+fn print_report(report: &Report, format: Format) -> Result<(), Error> {
+    match format {
+        Format::Text => println!("{}", report),
+        Format::Json => println!("{}", serde_json::to_string_pretty(report)?)
+    }
+    if report.exit_code() != 0 {
+        std::process::exit(report.exit_code())
+    }
+    Ok(())
+}
+
+/// Print what forgetting a site removed, or would remove.
+fn print_forget_report(report: &forget::ForgetReport) {
+    println!("Tabs:           {:>10}", report.tabs);
+    println!("Sessions:       {:>10}", report.sessions);
+    println!("Empty sessions: {:>10}", report.deleted_sessions);
+    println!("Thumbnails:     {:>10}", report.thumbnails);
+    println!("Undo tabs:      {:>10}", report.undo_tabs);
+    println!("Undo entries:   {:>10}", report.undo_entries);
+}
+
+/// Print changes to tabs like a diff, grouped by tab.
+fn print_changes(changes: &[Change]) {
+    for c in changes.iter() {
+        println!(
+            "session {} window {} tab {} {}",
+            c.session_id, c.window, c.tab, c.field
+        );
+        println!("- {}", c.old);
+        println!("+ {}", c.new);
+    }
+}
+
+/// Write an error to stderr, either as log message or as JSON object
+/// for scripts.
+fn report_error(e: &Error, path: Option<&Path>, format: Format) {
+    let kind = e.kind();
+    match format {
+        Format::Text => {
+            error!("{}", e);
+            if let Some(hint) = e.hint() {
+                info!("{}", hint);
+            }
+        }
+        Format::Json => {
+            let object = serde_json::json!({
+                "kind": kind,
+                "message": e.to_string(),
+                "path": path,
+                "hint": e.hint()
+            });
+            eprintln!("{}", object);
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use publicsuffix::{List, Psl};
+use regex::Regex;
+use serde::Serialize;
+use url::Url;
+
+use crate::session_buddy::database::saved_sessions;
+use crate::session_buddy::filter::Filter;
+use crate::session_buddy::session::SavedSession;
+use crate::Error;
+
+/// Snapshot of https://publicsuffix.org/list/public_suffix_list.dat
+const PUBLIC_SUFFIX_LIST: &str = include_str!("../../data/public_suffix_list.dat");
+
+/// Key URLs are grouped by.
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum GroupBy {
+    /// Registrable domain, e.g. `example.co.uk` for
+    /// `https://www.example.co.uk/`
+    #[default]
+    Domain,
+    /// URL scheme, e.g. `https` or `chrome`
+    Scheme,
+    /// Scheme, host and the first path segments
+    Prefix
+}
+
+/// Order of the reported groups.
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum SortBy {
+    /// Most tabs first
+    #[default]
+    Count,
+    /// Most sessions first
+    Sessions,
+    /// Earliest first seen first
+    FirstSeen,
+    /// Latest last seen first
+    LastSeen,
+    /// Alphabetically by key
+    Name
+}
+
+/// How to group, filter and sort URLs.
+#[derive(Default, Debug, Clone)]
+pub struct Options {
+    pub group_by: GroupBy,
+    /// Number of path segments of a prefix.
+    pub depth: usize,
+    /// Only count URLs with one of these schemes, all if empty.
+    pub schemes: Vec<String>,
+    /// Only report groups whose key matches.
+    pub pattern: Option<Regex>,
+    /// Only report groups with at least this many tabs.
+    pub min_count: u64,
+    pub sort_by: SortBy,
+    pub reverse: bool,
+    pub limit: Option<usize>
+}
+
+/// Tabs sharing a domain, scheme or prefix. Seen times are the
+/// creation times of the sessions containing them.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UrlGroup {
+    pub key: String,
+    pub tabs: u64,
+    pub sessions: u64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>
+}
+
+/// The bundled public suffix list.
+pub fn public_suffix_list() -> Result<List, Error> {
+    PUBLIC_SUFFIX_LIST
+        .parse()
+        .map_err(|e| Error::Format(format!("public suffix list: {}", e)))
+}
+
+/// Key of a URL when grouped by. URLs which fail to parse are
+/// grouped as `(invalid)`.
+pub fn key(list: &List, url: &str, by: GroupBy, depth: usize) -> String {
+    let url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return "(invalid)".to_string()
+    };
+
+    match by {
+        GroupBy::Scheme => url.scheme().to_string(),
+        GroupBy::Domain => match url.host_str() {
+            Some(host) => list
+                .domain(host.as_bytes())
+                .and_then(|d| std::str::from_utf8(d.as_bytes()).ok())
+                .unwrap_or(host)
+                .to_string(),
+            None => format!("({}:)", url.scheme())
+        },
+        GroupBy::Prefix if url.cannot_be_a_base() => format!("{}:", url.scheme()),
+        GroupBy::Prefix => {
+            let mut prefix = format!("{}://{}", url.scheme(), url.host_str().unwrap_or_default());
+            if let Some(segments) = url.path_segments() {
+                for segment in segments.filter(|s| !s.is_empty()).take(depth) {
+                    prefix.push('/');
+                    prefix.push_str(segment);
+                }
+            }
+            prefix
+        }
+    }
+}
+
+/// Group the tab URLs of sessions, then filter and sort the groups.
+pub fn group(list: &List, sessions: &[SavedSession], options: &Options) -> Vec<UrlGroup> {
+    let mut groups: HashMap<String, UrlGroup> = HashMap::new();
+
+    for session in sessions.iter() {
+        let seen = session.creation_date_time;
+        let mut keys = HashSet::new();
+
+        let urls = session
+            .windows
+            .iter()
+            .flat_map(|w| w.tabs.iter().flatten())
+            .filter_map(|t| t.url.as_deref())
+            .filter(|url| !url.is_empty());
+
+        for url in urls {
+            if !options.schemes.is_empty()
+                && !options.schemes.iter().any(|s| {
+                    url.split_once(':')
+                        .is_some_and(|(scheme, _)| scheme.eq_ignore_ascii_case(s))
+                })
+            {
+                continue;
+            }
+
+            let key = key(list, url, options.group_by, options.depth);
+            let group = groups.entry(key.clone()).or_insert_with(|| UrlGroup {
+                key: key.clone(),
+                tabs: 0,
+                sessions: 0,
+                first_seen: seen,
+                last_seen: seen
+            });
+
+            group.tabs += 1;
+            group.first_seen = group.first_seen.min(seen);
+            group.last_seen = group.last_seen.max(seen);
+            if keys.insert(key) {
+                group.sessions += 1;
+            }
+        }
+    }
+
+    let mut groups: Vec<UrlGroup> = groups
+        .into_values()
+        .filter(|g| g.tabs >= options.min_count)
+        .filter(|g| options.pattern.as_ref().is_none_or(|p| p.is_match(&g.key)))
+        .collect();
+
+    groups.sort_by(|a, b| {
+        let order = match options.sort_by {
+            SortBy::Count => b.tabs.cmp(&a.tabs),
+            SortBy::Sessions => b.sessions.cmp(&a.sessions),
+            SortBy::FirstSeen => a.first_seen.cmp(&b.first_seen),
+            SortBy::LastSeen => b.last_seen.cmp(&a.last_seen),
+            SortBy::Name => a.key.cmp(&b.key)
+        };
+        order.then_with(|| a.key.cmp(&b.key))
+    });
+
+    if options.reverse {
+        groups.reverse();
+    }
+
+    if let Some(limit) = options.limit {
+        groups.truncate(limit);
+    }
+
+    groups
+}
+
+/// Group the tab URLs of all sessions of a database.
+pub async fn domains(
+    db: &Path,
+    filter: &Filter,
+    options: &Options
+) -> Result<Vec<UrlGroup>, Error> {
+    let list = public_suffix_list()?;
+
+    let sessions: Vec<SavedSession> = saved_sessions(db)
+        .await?
+        .into_iter()
+        .filter(|s| filter.matches(s))
+        .collect();
+
+    Ok(group(&list, &sessions, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use sqlx::types::Json;
+
+    use super::{group, key, public_suffix_list, GroupBy, Options, SortBy};
+    use crate::chrome::tab::Tab;
+    use crate::chrome::window::Window;
+    use crate::session_buddy::session::SavedSession;
+
+    fn session(day: u32, urls: &[&str]) -> SavedSession {
+        SavedSession {
+            creation_date_time: Utc.with_ymd_and_hms(2023, 1, day, 0, 0, 0).unwrap(),
+            windows: Json(vec![Window {
+                tabs: Some(
+                    urls.iter()
+                        .map(|url| Tab {
+                            url: Some(url.to_string()),
+                            ..Default::default()
+                        })
+                        .collect()
+                ),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keys_urls() {
+        let list = public_suffix_list().unwrap();
+
+        let url = "https://www.bbc.co.uk/news/world?x=1";
+        assert_eq!(key(&list, url, GroupBy::Domain, 1), "bbc.co.uk");
+        assert_eq!(key(&list, url, GroupBy::Scheme, 1), "https");
+        assert_eq!(
+            key(&list, url, GroupBy::Prefix, 1),
+            "https://www.bbc.co.uk/news"
+        );
+        assert_eq!(key(&list, url, GroupBy::Prefix, 0), "https://www.bbc.co.uk");
+
+        assert_eq!(
+            key(&list, "http://localhost:8080/", GroupBy::Domain, 1),
+            "localhost"
+        );
+        assert_eq!(key(&list, "about:blank", GroupBy::Domain, 1), "(about:)");
+        assert_eq!(key(&list, "about:blank", GroupBy::Prefix, 1), "about:");
+        assert_eq!(key(&list, "not a url", GroupBy::Domain, 1), "(invalid)");
+    }
+
+    #[test]
+    fn groups_urls() {
+        let list = public_suffix_list().unwrap();
+        let sessions = [
+            session(
+                2,
+                &[
+                    "https://www.example.com/a",
+                    "https://mail.example.com/b",
+                    "ftp://files.test.org/",
+                    ""
+                ]
+            ),
+            session(1, &["https://example.com/c", "https://other.org/"]),
+            session(3, &["https://other.org/"])
+        ];
+
+        let groups = group(&list, &sessions, &Options::default());
+        let keys: Vec<&str> = groups.iter().map(|g| g.key.as_str()).collect();
+        assert_eq!(keys, ["example.com", "other.org", "test.org"]);
+
+        let example = &groups[0];
+        assert_eq!(example.tabs, 3);
+        assert_eq!(example.sessions, 2);
+        assert_eq!(example.first_seen, sessions[1].creation_date_time);
+        assert_eq!(example.last_seen, sessions[0].creation_date_time);
+
+        let other = &groups[1];
+        assert_eq!((other.tabs, other.sessions), (2, 2));
+        assert_eq!(other.first_seen, sessions[1].creation_date_time);
+        assert_eq!(other.last_seen, sessions[2].creation_date_time);
+
+        let by = |sort_by, reverse| {
+            let options = Options {
+                sort_by,
+                reverse,
+                ..Default::default()
+            };
+            group(&list, &sessions, &options)
+                .into_iter()
+                .map(|g| g.key)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            by(SortBy::LastSeen, false),
+            ["other.org", "example.com", "test.org"]
+        );
+        assert_eq!(
+            by(SortBy::FirstSeen, false),
+            ["example.com", "other.org", "test.org"]
+        );
+        assert_eq!(
+            by(SortBy::Name, true),
+            ["test.org", "other.org", "example.com"]
+        );
+        // Ties are broken by key.
+        assert_eq!(
+            by(SortBy::Sessions, false),
+            ["example.com", "other.org", "test.org"]
+        );
+
+        let options = Options {
+            schemes: vec!["HTTPS".to_string()],
+            min_count: 2,
+            limit: Some(1),
+            sort_by: SortBy::Name,
+            reverse: true,
+            ..Default::default()
+        };
+        let groups = group(&list, &sessions, &options);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].key, "other.org");
+    }
+}
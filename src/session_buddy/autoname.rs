@@ -0,0 +1,234 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::Connection;
+use url::Url;
+
+use crate::session_buddy::database::{connect, saved_sessions};
+use crate::session_buddy::filter::Filter;
+use crate::session_buddy::session::SavedSession;
+use crate::Error;
+
+/// Longest name suggested.
+const MAX_LEN: usize = 80;
+
+/// Words too common in titles to say anything about a session.
+const STOPWORDS: [&str; 32] = [
+    "the", "and", "for", "with", "from", "your", "you", "this", "that", "are", "how", "what",
+    "why", "when", "new", "not", "all", "can", "www", "com", "org", "net", "http", "https", "html",
+    "home", "page", "index", "welcome", "untitled", "tab", "login"
+];
+
+/// A name suggested for an unnamed session.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Suggestion {
+    pub id: i64,
+    pub created: DateTime<Utc>,
+    pub tabs: i32,
+    pub name: String
+}
+
+fn truncate(name: String) -> String {
+    match name.char_indices().nth(MAX_LEN) {
+        Some((i, _)) => format!("{}…", name[..i].trim_end()),
+        None => name
+    }
+}
+
+/// Keys ordered by count, most first, ties in order of appearance.
+fn top(counts: HashMap<String, (usize, usize)>, n: usize, min: usize) -> Vec<String> {
+    let mut counts: Vec<(String, (usize, usize))> =
+        counts.into_iter().filter(|(_, (c, _))| *c >= min).collect();
+    counts.sort_by_key(|(_, (count, first))| (Reverse(*count), *first));
+    counts.into_iter().take(n).map(|(k, _)| k).collect()
+}
+
+/// Suggest a name from the contents of a session: the titles of its
+/// windows if there are any, else its most common domains and the
+/// words most of its tab titles share. A session of a single tab is
+/// named after its title.
+pub fn suggest(session: &SavedSession) -> Option<String> {
+    let mut window_titles: Vec<&str> = vec![];
+    for title in session
+        .windows
+        .iter()
+        .filter_map(|w| w.nx_title.as_deref())
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+    {
+        if !window_titles.contains(&title) {
+            window_titles.push(title);
+        }
+    }
+    if !window_titles.is_empty() {
+        return Some(truncate(window_titles.join(", ")));
+    }
+
+    let tabs: Vec<_> = session
+        .windows
+        .iter()
+        .flat_map(|w| w.tabs.iter().flatten())
+        .collect();
+
+    if let [tab] = tabs.as_slice() {
+        if let Some(title) = tab
+            .title
+            .as_deref()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+        {
+            return Some(truncate(title.to_string()));
+        }
+    }
+
+    let mut domains = HashMap::new();
+    let mut words = HashMap::new();
+
+    for (i, tab) in tabs.iter().enumerate() {
+        let host = tab
+            .url
+            .as_deref()
+            .and_then(|u| Url::parse(u).ok())
+            .filter(|u| u.scheme() == "http" || u.scheme() == "https")
+            .and_then(|u| {
+                u.host_str()
+                    .map(|h| h.trim_start_matches("www.").to_lowercase())
+            });
+        if let Some(host) = host {
+            domains.entry(host).or_insert((0, i)).0 += 1;
+        }
+
+        let title = tab.title.as_deref().unwrap_or_default().to_lowercase();
+        let mut seen = HashSet::new();
+        for word in title
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.chars().count() >= 3)
+            .filter(|w| !w.chars().all(|c| c.is_numeric()))
+            .filter(|w| !STOPWORDS.contains(w))
+            .filter(|w| seen.insert(*w))
+        {
+            let order = words.len();
+            words.entry(word.to_string()).or_insert((0, order)).0 += 1;
+        }
+    }
+
+    let domains = top(domains, 2, 1);
+    let words = top(words, 3, 2);
+
+    let name = match (domains.is_empty(), words.is_empty()) {
+        (true, true) => return None,
+        (false, true) => domains.join(", "),
+        (true, false) => words.join(" "),
+        (false, false) => format!("{} - {}", domains.join(", "), words.join(" "))
+    };
+
+    Some(truncate(name))
+}
+
+/// Suggestions for all sessions without a name matching the filter.
+/// Sessions nothing can be suggested for are skipped.
+pub async fn suggestions(db: &Path, filter: &Filter) -> Result<Vec<Suggestion>, Error> {
+    Ok(saved_sessions(db)
+        .await?
+        .iter()
+        .filter(|s| s.name.trim().is_empty() && filter.matches(s))
+        .filter_map(|s| {
+            Some(Suggestion {
+                id: s.id?.into(),
+                created: s.creation_date_time,
+                tabs: s.count_tabs(),
+                name: suggest(s)?
+            })
+        })
+        .collect())
+}
+
+/// Name sessions. Nothing but the names is changed, not even the
+/// modification time. Returns the number of named sessions.
+pub async fn apply(db: &Path, names: &[(i64, String)]) -> Result<u64, Error> {
+    let mut conn = connect(db).await?;
+    let mut tx = conn.begin().await?;
+    let mut named = 0;
+
+    for (id, name) in names.iter() {
+        named += sqlx::query("UPDATE SavedSessions SET name = $1 WHERE id = $2")
+            .bind(name)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+    }
+
+    tx.commit().await?;
+
+    Ok(named)
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::types::Json;
+
+    use super::suggest;
+    use crate::chrome::tab::Tab;
+    use crate::chrome::window::Window;
+    use crate::session_buddy::session::SavedSession;
+
+    fn session(windows: Vec<Window>) -> SavedSession {
+        SavedSession {
+            windows: Json(windows),
+            ..Default::default()
+        }
+    }
+
+    fn window(tabs: &[(&str, &str)]) -> Window {
+        Window {
+            tabs: Some(
+                tabs.iter()
+                    .map(|(url, title)| Tab {
+                        url: Some(url.to_string()),
+                        title: Some(title.to_string()),
+                        ..Default::default()
+                    })
+                    .collect()
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn suggests_names() {
+        let tabs = window(&[
+            (
+                "https://github.com/tokio-rs/tokio",
+                "Tokio: async runtime for Rust"
+            ),
+            (
+                "https://www.github.com/smol-rs/smol",
+                "Smol: small async runtime"
+            ),
+            ("https://docs.rs/tokio", "tokio - Rust"),
+            ("chrome://newtab", "New Tab")
+        ]);
+        assert_eq!(
+            suggest(&session(vec![tabs.clone()])).as_deref(),
+            Some("github.com, docs.rs - tokio async runtime")
+        );
+
+        let titled = Window {
+            nx_title: Some("Research".to_string()),
+            ..tabs
+        };
+        assert_eq!(suggest(&session(vec![titled])).as_deref(), Some("Research"));
+
+        let single = window(&[("https://example.com", "Example Domain")]);
+        assert_eq!(
+            suggest(&session(vec![single])).as_deref(),
+            Some("Example Domain")
+        );
+
+        assert_eq!(suggest(&session(vec![])), None);
+    }
+}
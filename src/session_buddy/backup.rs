@@ -1,12 +1,16 @@
 use std::path::Path;
 
 use chrono::DateTime;
+use chrono::NaiveDate;
 use chrono::Utc;
-use serde::ser::StdError;
+use regex::Regex;
 use serde::Deserialize;
 use serde::Serialize;
+use sqlx::types::JsonValue;
 
 use crate::session_buddy::database;
+use crate::session_buddy::filter::Filter;
+use crate::session_buddy::report::Report;
 use crate::session_buddy::session::SavedSession;
 use crate::session_buddy::settings::get_datetime_value_setting;
 use crate::session_buddy::settings::get_string_value_setting;
@@ -17,6 +21,9 @@ use crate::session_buddy::SESSION_BUDDY_VERSION;
 use crate::util::get_language;
 use crate::util::get_platform;
 use crate::util::get_user_agent;
+use crate::Error;
+
+use schema::{Presence, Schema};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Backup {
@@ -36,12 +43,49 @@ pub struct Backup {
     pub user_settings: UserSettings
 }
 
+/// Sessions to put into a backup, on top of the filter. An empty
+/// selection selects all sessions.
+#[derive(Default, Debug, Clone)]
+pub struct Selection {
+    pub ids: Vec<i32>,
+    pub name: Option<Regex>,
+    /// Sessions need one of these tags.
+    pub tags: Vec<String>,
+    /// First and last day of creation, inclusive.
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>
+}
+
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+            && self.name.is_none()
+            && self.tags.is_empty()
+            && self.since.is_none()
+            && self.until.is_none()
+    }
+
+    pub fn matches(&self, session: &SavedSession) -> bool {
+        let created = session.creation_date_time.date_naive();
+
+        (self.ids.is_empty() || session.id.is_some_and(|id| self.ids.contains(&id)))
+            && self.name.as_ref().is_none_or(|r| r.is_match(&session.name))
+            && (self.tags.is_empty() || self.tags.iter().any(|t| session.has_tag(t)))
+            && self.since.is_none_or(|d| created >= d)
+            && self.until.is_none_or(|d| created <= d)
+    }
+}
+
 impl Backup {
-    pub async fn new(db: &Path) -> Result<Self, Box<dyn StdError>> {
+    pub async fn new(db: &Path, filter: &Filter, selection: &Selection) -> Result<Self, Error> {
+        // Like the extension, a backup of some sessions is scoped
+        // "selected" rather than "all".
+        let partial = !selection.is_empty() || filter.expr.is_some();
+
         let mut b = Backup {
             format: SESSION_BUDDY_FORMAT.to_string(),
             created: Utc::now(),
-            session_scope: "all".to_string(),
+            session_scope: if partial { "selected" } else { "all" }.to_string(),
             include_session: true,
             include_window: true,
             platform: get_platform(),
@@ -58,12 +102,17 @@ impl Backup {
             }
         };
 
-        let _ = &b.collect(db).await?;
+        let _ = &b.collect(db, filter, selection).await?;
 
         Ok(b)
     }
 
-    pub async fn collect(&mut self, db: &Path) -> Result<(), Box<dyn StdError>> {
+    pub async fn collect(
+        &mut self,
+        db: &Path,
+        filter: &Filter,
+        selection: &Selection
+    ) -> Result<(), Error> {
         // Previous sessions
         //self.sessions.extend(
         //    get_previous_sessions(db)
@@ -74,13 +123,34 @@ impl Backup {
         //);
 
         // Saved sessions
-        self.sessions.extend(
-            database::saved_sessions(db)
-                .await?
-                //.iter()
-                //.map(SavedSession::from)
-                //.collect::<Vec<SavedSession>>(),
-        );
+        let sessions: Vec<SavedSession> = database::saved_sessions(db)
+            .await?
+            .into_iter()
+            .filter(|s| filter.matches(s) && selection.matches(s))
+            .map(|mut s| {
+                // The database has no type, the extension won't import
+                // sessions without one.
+                if s.type_.is_empty() {
+                    s.type_ = "saved".to_string();
+                }
+                s
+            })
+            .collect();
+
+        let missing: Vec<String> = selection
+            .ids
+            .iter()
+            .filter(|id| !sessions.iter().any(|s| s.id == Some(**id)))
+            .map(i32::to_string)
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::NotFound(format!(
+                "no matching session with id {}",
+                missing.join(", ")
+            )));
+        }
+
+        self.sessions.extend(sessions);
 
         // Don't care about the current session
 
@@ -88,8 +158,387 @@ impl Backup {
     }
 }
 
-/// TODO This could be done better.
-pub async fn validate(path: &Path) -> Result<(), Box<dyn StdError>> {
-    let _: Backup = serde_json::from_str(std::fs::read_to_string(path)?.trim_start_matches('\u{feff}'))?;
-    Ok(())
+/// Validate a JSON backup. Instead of stopping at the first
+/// problem, the whole file is checked against the structure of
+/// Backup and every problem ends up in the returned report, located
+/// by its JSON path, e.g. `sessions[412].windows[3].tabs[17].url`.
+pub async fn validate(path: &Path) -> Result<Report, Error> {
+    Ok(validate_str(&std::fs::read_to_string(path)?))
+}
+
+/// Validate the contents of a JSON backup. See validate.
+pub fn validate_str(content: &str) -> Report {
+    let mut report = Report::new();
+
+    if content.starts_with('\u{feff}') {
+        report.warning("bom", ROOT, "file starts with a byte order mark");
+    }
+
+    match serde_json::from_str::<JsonValue>(content.trim_start_matches('\u{feff}')) {
+        Ok(value) => validate_backup(&mut report, &value),
+        Err(e) => report.error("syntax", ROOT, e.to_string())
+    }
+
+    report
+}
+
+/// Location of findings concerning the whole document.
+const ROOT: &str = "(root)";
+
+/// Session types known to the extension.
+const SESSION_TYPES: [&str; 3] = ["saved", "previous", "current"];
+
+/// Expected structure of a backup, used by validate.
+mod schema {
+    use chrono::DateTime;
+    use sqlx::types::JsonValue;
+
+    use Kind::*;
+    use Presence::*;
+
+    /// JSON type a field is expected to have.
+    #[derive(Debug, Clone, Copy)]
+    pub enum Kind {
+        Bool,
+        Integer,
+        String,
+        Timestamp,
+        Object,
+        Array,
+        /// Tags, either a comma separated string or a list.
+        Tags
+    }
+
+    impl Kind {
+        pub fn matches(&self, value: &JsonValue) -> bool {
+            match self {
+                Kind::Bool => value.is_boolean(),
+                Kind::Integer => value.is_i64(),
+                Kind::String => value.is_string(),
+                Kind::Timestamp => value
+                    .as_str()
+                    .is_some_and(|s| DateTime::parse_from_rfc3339(s).is_ok()),
+                Kind::Object => value.is_object(),
+                Kind::Array => value.is_array(),
+                Kind::Tags => value.is_string() || value.is_array()
+            }
+        }
+    }
+
+    /// Whether a field may be missing or null. Mirrors the serde
+    /// attributes of the structs a backup is deserialized into.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Presence {
+        /// Has to be present and must not be null.
+        Required,
+        /// May be missing, but must not be null.
+        Defaulted,
+        /// May be missing or null.
+        Optional
+    }
+
+    pub type Schema = [(&'static str, Kind, Presence)];
+
+    pub const BACKUP_SCHEMA: &Schema = &[
+        ("format", String, Required),
+        ("created", Timestamp, Required),
+        ("session_scope", String, Required),
+        ("include_session", Bool, Required),
+        ("include_window", Bool, Required),
+        ("platform", String, Required),
+        ("language", String, Required),
+        ("ua", String, Required),
+        ("sb_id", String, Required),
+        ("sb_version", String, Required),
+        ("sb_installation_id", String, Required),
+        ("sb_installed", Timestamp, Required),
+        ("sessions", Array, Required),
+        ("user_settings", Object, Required)
+    ];
+
+    pub const USER_SETTINGS_SCHEMA: &Schema = &[
+        ("sessionExport_Format", String, Optional),
+        ("sessionExport_Scope", String, Optional),
+        ("sessionExport_ShowTitles", String, Optional),
+        ("sessionExport_ShowURLs", String, Optional)
+    ];
+
+    pub const SESSION_SCHEMA: &Schema = &[
+        ("id", Integer, Optional),
+        ("gid", String, Optional),
+        ("type", String, Required),
+        ("name", String, Defaulted),
+        ("generated", Timestamp, Defaulted),
+        ("created", Timestamp, Defaulted),
+        ("modified", Timestamp, Defaulted),
+        ("tags", Tags, Defaulted),
+        ("sbh_tags", Tags, Optional),
+        ("deleted", String, Defaulted),
+        ("windows", Array, Required),
+        ("unfiltered_window_count", Integer, Defaulted),
+        ("filtered_window_count", Integer, Defaulted),
+        ("unfiltered_tab_count", Integer, Defaulted),
+        ("filtered_tab_count", Integer, Defaulted)
+    ];
+
+    pub const WINDOW_SCHEMA: &Schema = &[
+        ("nx_title", String, Optional),
+        ("alwaysOnTop", Bool, Defaulted),
+        ("focused", Bool, Defaulted),
+        ("height", Integer, Optional),
+        ("id", Integer, Optional),
+        ("incognito", Bool, Defaulted),
+        ("left", Integer, Optional),
+        ("sessionId", String, Optional),
+        ("state", String, Optional),
+        ("tabs", Array, Optional),
+        ("top", Integer, Optional),
+        ("type", String, Optional),
+        ("width", Integer, Optional)
+    ];
+
+    pub const TAB_SCHEMA: &Schema = &[
+        ("active", Bool, Defaulted),
+        ("audible", Bool, Optional),
+        ("autoDiscardable", Bool, Defaulted),
+        ("discarded", Bool, Defaulted),
+        ("favIconUrl", String, Optional),
+        ("groupId", Integer, Defaulted),
+        ("height", Integer, Optional),
+        ("highlighted", Bool, Defaulted),
+        ("id", Integer, Optional),
+        ("incognito", Bool, Defaulted),
+        ("index", Integer, Defaulted),
+        ("mutedInfo", Object, Optional),
+        ("openerTabId", Integer, Optional),
+        ("pendingUrl", String, Optional),
+        ("pinned", Bool, Defaulted),
+        ("selected", Bool, Defaulted),
+        ("sessionId", String, Optional),
+        ("status", String, Optional),
+        ("title", String, Optional),
+        ("url", String, Optional),
+        ("width", Integer, Optional),
+        ("windowId", Integer, Defaulted)
+    ];
+
+    pub const MUTED_INFO_SCHEMA: &Schema = &[
+        ("extensionId", String, Optional),
+        ("muted", Bool, Required),
+        ("reason", String, Optional)
+    ];
+}
+
+fn json_type(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "boolean",
+        JsonValue::Number(_) => "number",
+        JsonValue::String(_) => "string",
+        JsonValue::Array(_) => "array",
+        JsonValue::Object(_) => "object"
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path == ROOT {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Check the fields of an object against a schema. Returns the
+/// object if value is one at all.
+fn validate_object<'a>(
+    report: &mut Report,
+    path: &str,
+    value: &'a JsonValue,
+    schema: &Schema
+) -> Option<&'a serde_json::Map<String, JsonValue>> {
+    let Some(object) = value.as_object() else {
+        report.error(
+            "wrong_type",
+            path,
+            format!("expected object, found {}", json_type(value))
+        );
+        return None;
+    };
+
+    for (key, kind, presence) in schema.iter() {
+        match object.get(*key) {
+            None if *presence == Presence::Required => {
+                report.error("missing_field", join(path, key), "missing field")
+            }
+            None => {}
+            Some(JsonValue::Null) if *presence == Presence::Optional => {}
+            Some(v) if !kind.matches(v) => report.error(
+                "wrong_type",
+                join(path, key),
+                format!("expected {:?}, found {}", kind, json_type(v)).to_lowercase()
+            ),
+            Some(_) => {}
+        }
+    }
+
+    Some(object)
+}
+
+/// Array elements of a field, if there are any.
+fn elements<'a>(object: &'a serde_json::Map<String, JsonValue>, key: &str) -> &'a [JsonValue] {
+    object
+        .get(key)
+        .and_then(|v| v.as_array())
+        .map_or(&[], |v| v.as_slice())
+}
+
+fn validate_backup(report: &mut Report, value: &JsonValue) {
+    let Some(backup) = validate_object(report, ROOT, value, schema::BACKUP_SCHEMA) else {
+        return;
+    };
+
+    if let Some(user_settings) = backup.get("user_settings") {
+        validate_object(
+            report,
+            "user_settings",
+            user_settings,
+            schema::USER_SETTINGS_SCHEMA
+        );
+    }
+
+    for (i, session) in elements(backup, "sessions").iter().enumerate() {
+        validate_session(report, &format!("sessions[{}]", i), session);
+    }
+}
+
+fn validate_session(report: &mut Report, path: &str, value: &JsonValue) {
+    let Some(session) = validate_object(report, path, value, schema::SESSION_SCHEMA) else {
+        return;
+    };
+
+    if let Some(type_) = session.get("type").and_then(|v| v.as_str()) {
+        if !SESSION_TYPES.contains(&type_) {
+            report.warning(
+                "unknown_session_type",
+                join(path, "type"),
+                format!("unknown session type {:?}", type_)
+            );
+        }
+    }
+
+    for (i, window) in elements(session, "windows").iter().enumerate() {
+        let path = format!("{}.windows[{}]", path, i);
+        let Some(window) = validate_object(report, &path, window, schema::WINDOW_SCHEMA) else {
+            continue;
+        };
+
+        for (j, tab) in elements(window, "tabs").iter().enumerate() {
+            let path = format!("{}.tabs[{}]", path, j);
+            let Some(tab) = validate_object(report, &path, tab, schema::TAB_SCHEMA) else {
+                continue;
+            };
+
+            if let Some(muted_info) = tab.get("mutedInfo").filter(|v| !v.is_null()) {
+                validate_object(
+                    report,
+                    &join(&path, "mutedInfo"),
+                    muted_info,
+                    schema::MUTED_INFO_SCHEMA
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use regex::Regex;
+
+    use super::{validate_str, Backup, Selection};
+    use crate::session_buddy::report::Severity;
+    use crate::session_buddy::session::SavedSession;
+
+    #[test]
+    fn selects_sessions() {
+        let session = SavedSession {
+            id: Some(3),
+            name: "Project X".to_string(),
+            tags: r#"["work","rust"]"#.to_string(),
+            creation_date_time: Utc.with_ymd_and_hms(2023, 6, 1, 8, 0, 0).unwrap(),
+            ..Default::default()
+        };
+        let day = |d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok();
+
+        assert!(Selection::default().matches(&session));
+        assert!(Selection {
+            ids: vec![1, 3],
+            name: Regex::new("^Project").ok(),
+            tags: vec!["Work".to_string()],
+            since: day("2023-06-01"),
+            until: day("2023-06-01")
+        }
+        .matches(&session));
+        assert!(!Selection {
+            ids: vec![4],
+            ..Default::default()
+        }
+        .matches(&session));
+        assert!(!Selection {
+            tags: vec!["home".to_string()],
+            ..Default::default()
+        }
+        .matches(&session));
+        assert!(!Selection {
+            since: day("2023-06-02"),
+            ..Default::default()
+        }
+        .matches(&session));
+    }
+
+    #[test]
+    fn default_backup_is_valid() {
+        let json = serde_json::to_string(&Backup::default()).unwrap();
+        let report = validate_str(&json);
+        assert_eq!(report.findings, vec![]);
+    }
+
+    #[test]
+    fn findings_have_json_paths() {
+        let mut backup = serde_json::to_value(Backup::default()).unwrap();
+        backup["sessions"] = serde_json::json!([
+            { "type": "saved", "windows": [] },
+            { "type": "weird", "windows": [{ "tabs": [{ "url": 42 }, { "mutedInfo": {} }] }] }
+        ]);
+        backup.as_object_mut().unwrap().remove("ua");
+        let json = format!("\u{feff}{}", backup);
+
+        let report = validate_str(&json);
+        let found: Vec<(&str, &str)> = report
+            .findings
+            .iter()
+            .map(|f| (f.category, f.location.as_str()))
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                ("bom", "(root)"),
+                ("missing_field", "ua"),
+                ("unknown_session_type", "sessions[1].type"),
+                ("wrong_type", "sessions[1].windows[0].tabs[0].url"),
+                (
+                    "missing_field",
+                    "sessions[1].windows[0].tabs[1].mutedInfo.muted"
+                )
+            ]
+        );
+        assert_eq!(report.worst(), Some(Severity::Error));
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        let report = validate_str("{\"format\": ");
+        assert_eq!(report.summary().get("syntax"), Some(&1));
+    }
 }
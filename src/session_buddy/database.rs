@@ -1,55 +1,207 @@
-use std::error::Error;
+use std::collections::HashSet;
+use std::fmt;
 use std::fs::{self, File};
 use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::SystemTime;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use log::info;
-use serde::ser::StdError;
-use sqlx::sqlite::{SqliteConnectOptions, SqliteQueryResult};
-use sqlx::{ConnectOptions, SqliteConnection};
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteQueryResult, SqliteRow};
+use sqlx::types::Json;
+use sqlx::{ConnectOptions, Row, SqliteConnection};
 
 use crate::chrome::get_path;
-use crate::session_buddy::settings::{get_datetime_value_setting, get_string_value_setting};
+use crate::chrome::window::Window;
+use crate::session_buddy::settings::{
+    get_datetime_value_setting, get_string_value_setting, missing_required_settings
+};
 use crate::util::get_output_filename;
+use crate::Error;
 
-use super::backup::Backup;
+use super::backup::{Backup, Selection};
+use super::filter::Filter;
+use super::normalize::Rules;
+use super::redact::Redactor;
+use super::report::Report;
 use super::session::{PreviousSession, SavedSession};
 
 // Key "tags" is present on SavedSessions in the database
-// but not in JSON backups. Not ok.
+// but not in JSON backups of the extension. Ours carry them
+// as "sbh_tags".
+
+/// Distribution of a count over sessions or windows, using the
+/// nearest-rank method. All zero if there is nothing to count.
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub min: i32,
+    pub p50: i32,
+    pub p90: i32,
+    pub p99: i32,
+    pub max: i32
+}
+
+impl Percentiles {
+    pub fn new(mut values: Vec<i32>) -> Self {
+        if values.is_empty() {
+            return Percentiles::default();
+        }
+        values.sort_unstable();
+
+        let rank = |p: usize| values[(p * values.len()).div_ceil(100).max(1) - 1];
+
+        Percentiles {
+            min: values[0],
+            p50: rank(50),
+            p90: rank(90),
+            p99: rank(99),
+            max: values[values.len() - 1]
+        }
+    }
+}
 
-#[derive(Default)]
+/// Statistics about the sessions of a database and the database
+/// file itself.
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Stats {
+    pub path: PathBuf,
+    pub installation_id: String,
     pub sessions: i32,
     pub tabs: i32,
     pub windows: i32,
+    /// When the extension was installed.
     pub created: DateTime<Utc>,
-    pub modified: DateTime<Utc>,
+    /// When a session was last modified.
+    pub modified: Option<DateTime<Utc>>,
+    /// Last access of the database file.
     pub atime: DateTime<Utc>,
+    /// Last modification of the database file.
     pub mtime: DateTime<Utc>,
+    /// Last status change of the database file, its creation on
+    /// systems without status change times.
     pub ctime: DateTime<Utc>,
-    pub newest_session: DateTime<Utc>,
-    pub oldest_session: DateTime<Utc>,
-    pub duplicate_urls: i32
+    /// Creation time of the newest session.
+    pub newest_session: Option<DateTime<Utc>>,
+    /// Creation time of the oldest session.
+    pub oldest_session: Option<DateTime<Utc>>,
+    /// Tabs with a URL some other tab already has, compared after
+    /// canonicalizing them.
+    pub duplicate_urls: i32,
+    pub tabs_per_window: Percentiles,
+    pub windows_per_session: Percentiles
+}
+
+impl Stats {
+    /// Flat list of names and values, e.g. for one CSV record.
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let time = |t: Option<DateTime<Utc>>| t.map(|t| t.to_rfc3339()).unwrap_or_default();
+
+        let mut fields = [
+            ("path", self.path.display().to_string()),
+            ("installation_id", self.installation_id.clone()),
+            ("sessions", self.sessions.to_string()),
+            ("windows", self.windows.to_string()),
+            ("tabs", self.tabs.to_string()),
+            ("duplicate_urls", self.duplicate_urls.to_string()),
+            ("created", self.created.to_rfc3339()),
+            ("modified", time(self.modified)),
+            ("newest_session", time(self.newest_session)),
+            ("oldest_session", time(self.oldest_session)),
+            ("atime", self.atime.to_rfc3339()),
+            ("mtime", self.mtime.to_rfc3339()),
+            ("ctime", self.ctime.to_rfc3339())
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect::<Vec<_>>();
+
+        for (name, p) in [
+            ("tabs_per_window", &self.tabs_per_window),
+            ("windows_per_session", &self.windows_per_session)
+        ] {
+            for (suffix, value) in [
+                ("min", p.min),
+                ("p50", p.p50),
+                ("p90", p.p90),
+                ("p99", p.p99),
+                ("max", p.max)
+            ] {
+                fields.push((format!("{}_{}", name, suffix), value.to_string()));
+            }
+        }
+
+        fields
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |t: Option<DateTime<Utc>>| t.map(|t| t.to_string()).unwrap_or_default();
+        let percentiles = |p: &Percentiles| {
+            format!(
+                "min {} / p50 {} / p90 {} / p99 {} / max {}",
+                p.min, p.p50, p.p90, p.p99, p.max
+            )
+        };
+
+        writeln!(f, "Path:                {}", self.path.display())?;
+        writeln!(f, "Installation ID:     {}", self.installation_id)?;
+        writeln!(f, "Installation Date:   {}", self.created)?;
+        writeln!(f, "Tabs:                {:>5}", self.tabs)?;
+        writeln!(f, "Windows:             {:>5}", self.windows)?;
+        writeln!(f, "Sessions:            {:>5}", self.sessions)?;
+        writeln!(f, "Duplicate URLs:      {:>5}", self.duplicate_urls)?;
+        writeln!(
+            f,
+            "Tabs/window:         {}",
+            percentiles(&self.tabs_per_window)
+        )?;
+        writeln!(
+            f,
+            "Windows/session:     {}",
+            percentiles(&self.windows_per_session)
+        )?;
+        writeln!(f, "Newest session:      {}", time(self.newest_session))?;
+        writeln!(f, "Oldest session:      {}", time(self.oldest_session))?;
+        writeln!(f, "Last modified:       {}", time(self.modified))?;
+        writeln!(f, "File accessed:       {}", self.atime)?;
+        writeln!(f, "File modified:       {}", self.mtime)?;
+        write!(f, "File changed:        {}", self.ctime)
+    }
 }
 
+/// Tables created by the extension, see SCHEMA.
+const TABLES: [&str; 5] = [
+    "Settings",
+    "UserSettings",
+    "Undo",
+    "SavedSessions",
+    "PreviousSessions"
+];
+
+/// Chrome extensions didn't exist before 2009, so any older
+/// timestamp is bogus.
+pub(crate) const EARLIEST_TIMESTAMP: i64 = 1_230_768_000;
+
 //struct SessionStats {}
 //struct TabStats {}
 
 /// Get a new connection to a SQLite database.
-pub async fn connect(filename: &Path) -> Result<SqliteConnection, sqlx::Error> {
-    SqliteConnectOptions::from_str(format!("sqlite://{}", filename.display()).as_str())?
-        .log_statements(log::LevelFilter::Trace)
-        .create_if_missing(false)
-        .connect()
-        .await
+pub async fn connect(filename: &Path) -> Result<SqliteConnection, Error> {
+    Ok(
+        SqliteConnectOptions::from_str(format!("sqlite://{}", filename.display()).as_str())?
+            .log_statements(log::LevelFilter::Trace)
+            .create_if_missing(false)
+            .connect()
+            .await?
+    )
 }
 
 /// Create a new SQLite database with the Session Buddy
 /// schema.
-pub async fn create(filename: &Path) -> Result<SqliteQueryResult, sqlx::Error> {
+pub async fn create(filename: &Path) -> Result<SqliteQueryResult, Error> {
     let mut conn =
         SqliteConnectOptions::from_str(format!("sqlite://{}", filename.display()).as_str())?
             .log_statements(log::LevelFilter::Trace)
@@ -57,15 +209,15 @@ pub async fn create(filename: &Path) -> Result<SqliteQueryResult, sqlx::Error> {
             .connect()
             .await?;
 
-    sqlx::query(SCHEMA).execute(&mut conn).await
+    Ok(sqlx::query(SCHEMA).execute(&mut conn).await?)
 }
 
 /// Insert a serialized SavedSession into the database.
 pub async fn insert_saved_session(
     conn: &mut SqliteConnection,
     session: &SavedSession
-) -> Result<SqliteQueryResult, sqlx::Error> {
-    sqlx::query(
+) -> Result<SqliteQueryResult, Error> {
+    Ok(sqlx::query(
         r#"
         INSERT INTO SavedSessions (
             -- id -- AUTOINCREMENTED
@@ -97,30 +249,116 @@ pub async fn insert_saved_session(
     .bind(session.count_tabs())
     .bind(session.count_tabs())
     .execute(conn)
-    .await
+    .await?)
+}
+
+/// Get a SavedSession by id.
+pub async fn saved_session(conn: &mut SqliteConnection, id: i64) -> Result<SavedSession, Error> {
+    sqlx::query_as("SELECT * FROM SavedSessions WHERE id = ?")
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("SavedSessions has no session {}", id)))
+}
+
+/// Write back the windows of a SavedSession, recomputing its counts
+/// and setting its modification time to now.
+pub async fn update_saved_session_windows(
+    conn: &mut SqliteConnection,
+    session: &SavedSession
+) -> Result<SqliteQueryResult, Error> {
+    Ok(sqlx::query(
+        r#"
+        UPDATE SavedSessions SET
+            modificationDateTime = $1,
+            windows = $2,
+            unfilteredWindowCount = $3,
+            filteredWindowCount = $3,
+            unfilteredTabCount = $4,
+            filteredTabCount = $4
+        WHERE id = $5
+        "#
+    )
+    .bind(Utc::now())
+    .bind(&session.windows)
+    .bind(session.count_windows())
+    .bind(session.count_tabs())
+    .bind(session.id)
+    .execute(conn)
+    .await?)
+}
+
+/// Write back the tags of a SavedSession and set its modification
+/// time to now.
+pub async fn update_saved_session_tags(
+    conn: &mut SqliteConnection,
+    session: &SavedSession
+) -> Result<SqliteQueryResult, Error> {
+    Ok(
+        sqlx::query("UPDATE SavedSessions SET modificationDateTime = $1, tags = $2 WHERE id = $3")
+            .bind(Utc::now())
+            .bind(&session.tags)
+            .bind(session.id)
+            .execute(conn)
+            .await?
+    )
+}
+
+/// Flag a SavedSession as deleted, like the extension does, so it
+/// can be restored from the trash.
+pub async fn flag_saved_session_deleted(
+    conn: &mut SqliteConnection,
+    id: i64
+) -> Result<SqliteQueryResult, Error> {
+    Ok(sqlx::query(
+        "UPDATE SavedSessions SET deleted = 'true', modificationDateTime = $1 WHERE id = $2"
+    )
+    .bind(Utc::now())
+    .bind(id)
+    .execute(conn)
+    .await?)
+}
+
+/// Permanently delete a SavedSession.
+pub async fn delete_saved_session(
+    conn: &mut SqliteConnection,
+    id: i32
+) -> Result<SqliteQueryResult, Error> {
+    Ok(sqlx::query("DELETE FROM SavedSessions WHERE id = ?")
+        .bind(id)
+        .execute(conn)
+        .await?)
 }
 
 /// Gets all SavedSessions from Session Buddy JSON exports
 /// and returns them in a Vec<SavedSession>.
-pub async fn collect_saved_sessions(
-    files: &[PathBuf]
-) -> Result<Vec<SavedSession>, std::io::Error> {
+pub async fn collect_saved_sessions(files: &[PathBuf]) -> Result<Vec<SavedSession>, Error> {
     let mut saved_sessions: Vec<SavedSession> = vec![];
 
     for file in files.iter() {
         let j: serde_json::Value =
             serde_json::from_str(fs::read_to_string(file)?.trim_start_matches('\u{feff}'))?;
 
-        if let Some(sessions) = j.get("sessions") {
-            saved_sessions.append(
-                &mut sessions
-                    .as_array()
-                    .unwrap()
-                    .iter()
-                    .filter(|x| x.get("type").expect("SESSION HAS NO TYPE KEY") != "current")
-                    .map(SavedSession::from)
-                    .collect::<Vec<SavedSession>>()
-            );
+        let Some(sessions) = j.get("sessions") else {
+            continue;
+        };
+
+        let sessions = sessions
+            .as_array()
+            .ok_or_else(|| Error::Format(format!("{}: sessions is not a list", file.display())))?;
+
+        for (i, session) in sessions.iter().enumerate() {
+            match session.get("type") {
+                Some(t) if t == "current" => {}
+                Some(_) => saved_sessions.push(SavedSession::try_from(session)?),
+                None => {
+                    return Err(Error::Format(format!(
+                        "{}: sessions[{}] has no type",
+                        file.display(),
+                        i
+                    )))
+                }
+            }
         }
     }
 
@@ -130,44 +368,282 @@ pub async fn collect_saved_sessions(
 pub async fn import(
     conn: &mut SqliteConnection,
     saved_sessions: &Vec<SavedSession>
-) -> Result<(), sqlx::Error> {
+) -> Result<(), Error> {
     for session in saved_sessions {
         insert_saved_session(conn, session).await?;
     }
     Ok(())
 }
 
-pub fn copy_db(db: &Path, out: PathBuf) -> Result<(), std::io::Error> {
+pub fn copy_db(db: &Path, out: PathBuf) -> Result<(), Error> {
     let o = get_output_filename(db, Some(out))?;
     fs::copy(db, o)?;
     Ok(())
 }
 
-/// Run `PRAGMA integrity_check` and import the Database
-/// into a struct. If that succeeds, the
-/// database can be considered ok.
-pub async fn validate(path: &Path) -> Result<(), Box<dyn StdError>> {
+/// Run `PRAGMA integrity_check` and check the contents of the
+/// database for things that matter to Session Buddy: every
+/// `windows` column has to parse as a list of windows, stored counts
+/// have to match the actual number of windows and tabs, timestamps
+/// have to be sane and in order and all required settings have to be
+/// present. Every problem found ends up in the returned report.
+pub async fn validate(path: &Path) -> Result<Report, Error> {
     let mut conn = connect(path).await?;
-    sqlx::query("PRAGMA integrity_check")
-        .execute(&mut conn)
+    let mut report = Report::new();
+
+    let integrity: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(&mut conn)
         .await?;
 
-    // TODO We need a custom deserializer to handle all SavedSession,
-    // PreviousSession and CurrentSession as an enum
-    // Session::SavedSession etc. (Or just not to care about that would
-    // be another option). Who cares about PreviousSession and
-    // CurrentSession? They are automatically generated anyway.
-    //let backup = Backup::new(path).await?;
-    //serde_json::to_string(&backup)?;
+    for line in integrity.iter().filter(|l| l.as_str() != "ok") {
+        report.error(
+            "integrity",
+            "database",
+            format!("integrity check: {}", line)
+        );
+    }
 
-    Ok(())
+    let tables: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table'")
+            .fetch_all(&mut conn)
+            .await?;
+
+    let missing: Vec<&str> = TABLES
+        .into_iter()
+        .filter(|t| !tables.iter().any(|x| x == t))
+        .collect();
+
+    for table in missing.iter() {
+        report.error("table", *table, "table is missing");
+    }
+
+    // Without all tables there is not much left to check.
+    if !missing.is_empty() {
+        return Ok(report);
+    }
+
+    for key in missing_required_settings(&mut conn).await? {
+        report.error(
+            "setting",
+            "Settings",
+            format!("required key {} is missing", key)
+        );
+    }
+
+    let rows = sqlx::query(
+        r#"
+        SELECT id, deleted, generationDateTime, creationDateTime,
+            modificationDateTime, windows, unfilteredWindowCount,
+            unfilteredTabCount
+        FROM SavedSessions
+        "#
+    )
+    .fetch_all(&mut conn)
+    .await?;
+
+    for row in rows.iter() {
+        let location = format!("SavedSessions[{}]", row.try_get::<i64, _>("id")?);
+        validate_deleted(&mut report, &location, row);
+
+        let timestamps = [
+            "generationDateTime",
+            "creationDateTime",
+            "modificationDateTime"
+        ]
+        .map(|column| validate_timestamp(&mut report, &location, row, column));
+
+        if let [Some(generated), Some(created), Some(modified)] = timestamps {
+            if generated > modified {
+                report.warning(
+                    "timestamp",
+                    &location,
+                    "generated after it was last modified"
+                );
+            }
+            if created > modified {
+                report.warning("timestamp", &location, "created after it was last modified");
+            }
+        }
+
+        validate_windows(&mut report, &location, row);
+    }
+
+    let rows = sqlx::query(
+        r#"
+        SELECT id, deleted, recordingDateTime, creationDateTime, windows,
+            unfilteredWindowCount, unfilteredTabCount
+        FROM PreviousSessions
+        "#
+    )
+    .fetch_all(&mut conn)
+    .await?;
+
+    for row in rows.iter() {
+        let location = format!("PreviousSessions[{}]", row.try_get::<i64, _>("id")?);
+        validate_deleted(&mut report, &location, row);
+
+        let timestamps = ["creationDateTime", "recordingDateTime"]
+            .map(|column| validate_timestamp(&mut report, &location, row, column));
+
+        if let [Some(created), Some(recorded)] = timestamps {
+            if created > recorded {
+                report.warning("timestamp", &location, "created after it was recorded");
+            }
+        }
+
+        validate_windows(&mut report, &location, row);
+    }
+
+    Ok(report)
+}
+
+fn validate_deleted(report: &mut Report, location: &str, row: &SqliteRow) {
+    match row.try_get::<Option<String>, _>("deleted") {
+        Ok(Some(d)) if d == "true" || d == "false" => {}
+        Ok(d) => report.warning(
+            "deleted",
+            location,
+            format!("deleted is {:?}, expected 'true' or 'false'", d)
+        ),
+        Err(e) => report.error(
+            "deleted",
+            location,
+            format!("deleted cannot be read: {}", e)
+        )
+    }
+}
+
+fn validate_timestamp(
+    report: &mut Report,
+    location: &str,
+    row: &SqliteRow,
+    column: &str
+) -> Option<DateTime<Utc>> {
+    match row.try_get::<Option<DateTime<Utc>>, _>(column) {
+        Ok(Some(ts)) => {
+            if ts.timestamp() < EARLIEST_TIMESTAMP || ts > Utc::now() + Duration::days(1) {
+                report.warning(
+                    "timestamp",
+                    location,
+                    format!("{} is out of range: {}", column, ts)
+                );
+            }
+            Some(ts)
+        }
+        Ok(None) => {
+            report.error("timestamp", location, format!("{} is NULL", column));
+            None
+        }
+        Err(e) => {
+            report.error(
+                "timestamp",
+                location,
+                format!("{} is not a timestamp: {}", column, e)
+            );
+            None
+        }
+    }
+}
+
+fn validate_windows(report: &mut Report, location: &str, row: &SqliteRow) {
+    let windows: Vec<Window> = match row.try_get::<Option<String>, _>("windows") {
+        Ok(Some(w)) => match serde_json::from_str(&w) {
+            Ok(w) => w,
+            Err(e) => {
+                report.error(
+                    "windows",
+                    location,
+                    format!("windows is not a list of windows: {}", e)
+                );
+                return;
+            }
+        },
+        Ok(None) => {
+            report.error("windows", location, "windows is NULL");
+            return;
+        }
+        Err(e) => {
+            report.error(
+                "windows",
+                location,
+                format!("windows cannot be read: {}", e)
+            );
+            return;
+        }
+    };
+
+    let mut complete = true;
+
+    for (i, window) in windows.iter().enumerate() {
+        match &window.tabs {
+            Some(tabs) => {
+                for (j, tab) in tabs.iter().enumerate() {
+                    if tab.url.as_deref().unwrap_or_default().is_empty() {
+                        report.warning(
+                            "url",
+                            format!("{}.windows[{}].tabs[{}]", location, i, j),
+                            "tab has an empty URL"
+                        );
+                    }
+                }
+            }
+            None => {
+                complete = false;
+                report.error(
+                    "tabs",
+                    format!("{}.windows[{}]", location, i),
+                    "window has no tabs array"
+                );
+            }
+        }
+    }
+
+    // Counting tabs of windows without a tabs array is pointless.
+    if !complete {
+        return;
+    }
+
+    let session = SavedSession {
+        windows: Json(windows),
+        ..Default::default()
+    };
+
+    let counts = [
+        ("unfilteredWindowCount", session.count_windows()),
+        ("unfilteredTabCount", session.count_tabs())
+    ];
+
+    for (column, actual) in counts {
+        match row.try_get::<Option<i32>, _>(column) {
+            Ok(Some(stored)) if stored == actual => {}
+            Ok(stored) => report.warning(
+                "count",
+                location,
+                format!("{} is {:?}, but there are {}", column, stored, actual)
+            ),
+            Err(e) => report.error(
+                "count",
+                location,
+                format!("{} cannot be read: {}", column, e)
+            )
+        }
+    }
 }
 
 /// Export a Session Buddy database to a JSON file, similar
 /// to what the extension would produce. The file should be
-/// fit to be imported into a database again.
-pub async fn backup(db: &Path, out: Option<PathBuf>) -> Result<(), Box<dyn StdError>> {
-    let backup = Backup::new(db).await?;
+/// fit to be imported into a database again, redacted or not.
+pub async fn backup(
+    db: &Path,
+    out: Option<PathBuf>,
+    filter: &Filter,
+    selection: &Selection,
+    redactor: Option<&Redactor>
+) -> Result<(), Error> {
+    let mut backup = Backup::new(db, filter, selection).await?;
+    if let Some(redactor) = redactor {
+        redactor.backup(&mut backup);
+    }
     if out.is_some() {
         let fallback = PathBuf::from(".");
         let what = out.unwrap_or(fallback);
@@ -177,13 +653,13 @@ pub async fn backup(db: &Path, out: Option<PathBuf>) -> Result<(), Box<dyn StdEr
         writer.flush()?;
     } else {
         // Print JSON to stdout
-        println!("{}", serde_json::to_string(&backup).unwrap());
+        println!("{}", serde_json::to_string(&backup)?);
     }
     Ok(())
 }
 
 /// Traverse a fs path to find Session Buddy databases.
-pub async fn search(basepath: Option<PathBuf>) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+pub async fn search(basepath: Option<PathBuf>) -> Result<Vec<PathBuf>, Error> {
     // If no path is given, first try to figure out a platform
     // dependend path, and if that fails default to the
     // current working path.
@@ -224,7 +700,7 @@ pub async fn search(basepath: Option<PathBuf>) -> Result<Vec<PathBuf>, Box<dyn E
     Ok(dbs)
 }
 
-pub async fn saved_sessions(db: &Path) -> Result<Vec<SavedSession>, Box<dyn StdError>> {
+pub async fn saved_sessions(db: &Path) -> Result<Vec<SavedSession>, Error> {
     let mut conn = connect(db).await?;
 
     Ok(
@@ -234,7 +710,7 @@ pub async fn saved_sessions(db: &Path) -> Result<Vec<SavedSession>, Box<dyn StdE
     )
 }
 
-pub async fn previous_sessions(db: &Path) -> Result<Vec<PreviousSession>, Box<dyn StdError>> {
+pub async fn previous_sessions(db: &Path) -> Result<Vec<PreviousSession>, Error> {
     let mut conn = connect(db).await?;
 
     Ok(
@@ -245,21 +721,22 @@ pub async fn previous_sessions(db: &Path) -> Result<Vec<PreviousSession>, Box<dy
 }
 
 /// Print all URLs to stdout
-pub async fn dump(path: &Path) -> Result<(), Box<dyn StdError>> {
-    for session in saved_sessions(path).await? {
+pub async fn dump(path: &Path, filter: &Filter) -> Result<(), Error> {
+    for session in saved_sessions(path)
+        .await?
+        .iter()
+        .filter(|s| filter.matches(s))
+    {
         for window in session.windows.iter() {
-            match &window.tabs {
-                Some(tabs) => {
-                    for tab in tabs.iter() {
-                        match &tab.url {
-                            Some(url) => {
-                                println!("{}", url);
-                            }
-                            None => {}
-                        }
+            if let Some(tabs) = &window.tabs {
+                for tab in tabs
+                    .iter()
+                    .filter(|t| filter.matches_tab(session, window, t))
+                {
+                    if let Some(url) = &tab.url {
+                        println!("{}", url);
                     }
                 }
-                None => {}
             }
         }
     }
@@ -267,32 +744,78 @@ pub async fn dump(path: &Path) -> Result<(), Box<dyn StdError>> {
     Ok(())
 }
 
-pub async fn id(path: &Path) -> Result<String, sqlx::Error> {
+pub async fn id(path: &Path) -> Result<String, Error> {
     get_string_value_setting(path, "Settings", "installationID").await
 }
 
-pub async fn stats(path: &Path) -> Result<(), Box<dyn StdError>> {
-    let installation_id = get_string_value_setting(path, "Settings", "installationID").await?;
+/// Last status change of a file, or its creation where there is no
+/// such thing.
+#[cfg(unix)]
+fn ctime(metadata: &fs::Metadata) -> Result<DateTime<Utc>, Error> {
+    use std::os::unix::fs::MetadataExt;
 
-    let installation_date =
-        get_datetime_value_setting(path, "Settings", "installationTimeStamp").await?;
-
-    let sessions = saved_sessions(path).await?;
-
-    let session_count = sessions.len();
+    Ok(DateTime::<Utc>::from(SystemTime::UNIX_EPOCH)
+        + Duration::seconds(metadata.ctime())
+        + Duration::nanoseconds(metadata.ctime_nsec()))
+}
 
-    let window_count: i32 = sessions.iter().map(|s| s.count_windows()).sum();
+#[cfg(not(unix))]
+fn ctime(metadata: &fs::Metadata) -> Result<DateTime<Utc>, Error> {
+    Ok(metadata.created()?.into())
+}
 
-    let tab_count: i32 = sessions.iter().map(|s| s.count_tabs()).sum();
+pub async fn stats(path: &Path, filter: &Filter, rules: &Rules) -> Result<Stats, Error> {
+    let installation_id = get_string_value_setting(path, "Settings", "installationID").await?;
 
-    println!("Path:              {}", path.display());
-    println!("Installation ID:   {}", installation_id);
-    println!("Installation Date: {}", installation_date);
-    println!("Tabs:              {:>5}", tab_count);
-    println!("Windows:           {:>5}", window_count);
-    println!("Sessions:          {:>5}", session_count);
+    let installation_date =
+        get_datetime_value_setting(path, "Settings", "installationTimeStamp").await?;
 
-    Ok(())
+    let metadata = fs::metadata(path)?;
+
+    let sessions: Vec<SavedSession> = saved_sessions(path)
+        .await?
+        .into_iter()
+        .filter(|s| filter.matches(s))
+        .collect();
+
+    let mut urls = HashSet::new();
+    let duplicate_urls = sessions
+        .iter()
+        .flat_map(|s| s.windows.iter())
+        .flat_map(|w| w.tabs.iter().flatten())
+        .filter_map(|t| t.url.as_deref())
+        .filter(|url| !urls.insert(rules.normalize(url)))
+        .count();
+
+    let tabs_per_window = sessions
+        .iter()
+        .flat_map(|s| s.windows.iter())
+        .map(|w| {
+            w.tabs
+                .as_ref()
+                .map_or(0, |t| t.len())
+                .try_into()
+                .unwrap_or(i32::MAX)
+        })
+        .collect();
+
+    Ok(Stats {
+        path: path.to_path_buf(),
+        installation_id,
+        sessions: sessions.len().try_into().unwrap_or(i32::MAX),
+        tabs: sessions.iter().map(|s| s.count_tabs()).sum(),
+        windows: sessions.iter().map(|s| s.count_windows()).sum(),
+        created: installation_date,
+        modified: sessions.iter().map(|s| s.modification_date_time).max(),
+        atime: metadata.accessed()?.into(),
+        mtime: metadata.modified()?.into(),
+        ctime: ctime(&metadata)?,
+        newest_session: sessions.iter().map(|s| s.creation_date_time).max(),
+        oldest_session: sessions.iter().map(|s| s.creation_date_time).min(),
+        duplicate_urls: duplicate_urls.try_into().unwrap_or(i32::MAX),
+        tabs_per_window: Percentiles::new(tabs_per_window),
+        windows_per_session: Percentiles::new(sessions.iter().map(|s| s.count_windows()).collect())
+    })
 }
 
 const SCHEMA: &str = r#"
@@ -345,3 +868,141 @@ CREATE TABLE PreviousSessions (
     unfilteredTabCount INTEGER,
     filteredTabCount INTEGER);
 "#;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use tempfile::TempDir;
+
+    use chrono::Utc;
+    use sqlx::types::Json;
+
+    use super::{
+        collect_saved_sessions, connect, create, import, saved_sessions, validate, Percentiles
+    };
+    use crate::session_buddy::backup::{validate_str, Backup};
+    use crate::session_buddy::session::SavedSession;
+
+    /// A new empty database in a temporary directory, which is
+    /// removed when the returned guard is dropped.
+    pub async fn scratch_db() -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("db");
+        create(&db).await.unwrap();
+        (dir, db)
+    }
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        assert_eq!(Percentiles::new(vec![]), Percentiles::default());
+
+        let p = Percentiles::new((1..=10).rev().collect());
+        assert_eq!((p.min, p.p50, p.p90, p.p99, p.max), (1, 5, 9, 10, 10));
+
+        let p = Percentiles::new(vec![7]);
+        assert_eq!((p.min, p.p50, p.p99, p.max), (7, 7, 7, 7));
+    }
+
+    #[tokio::test]
+    async fn validate_reports_bad_rows() {
+        let (_dir, db) = scratch_db().await;
+        let mut conn = connect(&db).await.unwrap();
+
+        // Values are NUMERIC, integers have to be fine too.
+        sqlx::query(
+            "INSERT INTO Settings (key, value) VALUES ('installationID', 'x'), ('count', 42)"
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        for (deleted, windows, tabs) in [
+            ("false", r#"[{"tabs": [{"url": "https://a.com/"}]}]"#, 1),
+            ("maybe", "[{", 0),
+            ("true", r#"[{"tabs": []}]"#, 3)
+        ] {
+            sqlx::query(
+                r#"
+                INSERT INTO SavedSessions (deleted, windows, generationDateTime,
+                    creationDateTime, modificationDateTime, unfilteredWindowCount,
+                    unfilteredTabCount)
+                VALUES ($1, $2, $3, $3, $3, 1, $4)
+                "#
+            )
+            .bind(deleted)
+            .bind(windows)
+            .bind(Utc::now())
+            .bind(tabs)
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        }
+
+        let report = validate(&db).await.unwrap();
+        let findings: Vec<(&str, &str)> = report
+            .findings
+            .iter()
+            .map(|f| (f.category, f.location.as_str()))
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                ("setting", "Settings"),
+                ("deleted", "SavedSessions[2]"),
+                ("windows", "SavedSessions[2]"),
+                ("count", "SavedSessions[3]")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn imports_tags_from_backups() {
+        let (dir, db) = scratch_db().await;
+
+        let backup = Backup {
+            sessions: vec![SavedSession {
+                type_: "saved".to_string(),
+                tags: r#"["work","rust"]"#.to_string(),
+                windows: Json(vec![]),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&backup).unwrap();
+        assert!(json.contains(r#""sbh_tags":["work","rust"]"#));
+        assert_eq!(validate_str(&json).findings, vec![]);
+        fs::write(dir.path().join("backup.json"), json).unwrap();
+
+        // Under the alias and as found in the tags column.
+        let mut other = serde_json::to_value(Backup::default()).unwrap();
+        other["sessions"] = serde_json::json!([
+            {"type": "saved", "tags": ["alias"], "windows": []},
+            {"type": "saved", "sbh_tags": "old, style", "windows": []}
+        ]);
+        let json = other.to_string();
+        assert_eq!(validate_str(&json).findings, vec![]);
+        fs::write(dir.path().join("other.json"), json).unwrap();
+
+        let sessions = collect_saved_sessions(&[
+            dir.path().join("backup.json"),
+            dir.path().join("other.json")
+        ])
+        .await
+        .unwrap();
+        import(&mut connect(&db).await.unwrap(), &sessions)
+            .await
+            .unwrap();
+
+        let tags: Vec<Vec<String>> = saved_sessions(&db)
+            .await
+            .unwrap()
+            .iter()
+            .map(|s| s.tag_list())
+            .collect();
+        assert_eq!(
+            tags,
+            [vec!["work", "rust"], vec!["alias"], vec!["old", "style"]]
+        );
+    }
+}
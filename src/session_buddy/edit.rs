@@ -0,0 +1,346 @@
+//! Editing saved sessions. Window and tab positions start at 0, as
+//! printed by `sbh where`. Every change recomputes the counts of the
+//! changed sessions and sets their modification time to now.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use chrono::Utc;
+use sqlx::types::Json;
+use sqlx::Connection;
+
+use crate::chrome::tab::Tab;
+use crate::chrome::window::Window;
+use crate::session_buddy::database::{
+    connect, delete_saved_session, flag_saved_session_deleted, insert_saved_session, saved_session,
+    update_saved_session_windows
+};
+use crate::session_buddy::session::SavedSession;
+use crate::Error;
+
+/// Rename a session.
+pub async fn rename(db: &Path, id: i64, name: &str) -> Result<(), Error> {
+    let mut conn = connect(db).await?;
+
+    let result =
+        sqlx::query("UPDATE SavedSessions SET name = $1, modificationDateTime = $2 WHERE id = $3")
+            .bind(name)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&mut conn)
+            .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!(
+            "SavedSessions has no session {}",
+            id
+        )));
+    }
+
+    Ok(())
+}
+
+/// Append the windows of all other sessions to the first one and
+/// flag the others as deleted, so they can be restored from the
+/// trash. The merged session gets the tags of all of them and the
+/// given name, if any. Repeated ids are merged once. Returns the
+/// number of distinct sessions merged.
+pub async fn merge(db: &Path, ids: &[i64], name: Option<&str>) -> Result<usize, Error> {
+    let Some((first, others)) = ids.split_first() else {
+        return Ok(0);
+    };
+
+    let mut conn = connect(db).await?;
+    let mut tx = conn.begin().await?;
+
+    let mut merged = saved_session(&mut tx, *first).await?;
+    let mut tags = merged.tag_list();
+
+    let mut seen = HashSet::from([*first]);
+    for id in others.iter().filter(|id| seen.insert(**id)) {
+        let session = saved_session(&mut tx, *id).await?;
+        tags.extend(session.tag_list());
+        merged.windows.extend(session.windows.0);
+        flag_saved_session_deleted(&mut tx, *id).await?;
+    }
+
+    for tag in tags {
+        merged.add_tag(&tag);
+    }
+    if let Some(name) = name {
+        merged.name = name.to_string();
+    }
+
+    sqlx::query("UPDATE SavedSessions SET name = $1, tags = $2 WHERE id = $3")
+        .bind(&merged.name)
+        .bind(&merged.tags)
+        .bind(first)
+        .execute(&mut *tx)
+        .await?;
+    update_saved_session_windows(&mut tx, &merged).await?;
+
+    tx.commit().await?;
+
+    Ok(seen.len())
+}
+
+/// Move every window but the first of a session into a new session
+/// of its own, named after the session and numbered from 2. Returns
+/// the ids of the new sessions.
+pub async fn split(db: &Path, id: i64) -> Result<Vec<i64>, Error> {
+    let mut conn = connect(db).await?;
+    let mut tx = conn.begin().await?;
+
+    let mut session = saved_session(&mut tx, id).await?;
+    if session.windows.len() < 2 {
+        return Ok(vec![]);
+    }
+
+    let now = Utc::now();
+    let mut ids = vec![];
+
+    for (i, window) in session.windows.split_off(1).into_iter().enumerate() {
+        let new = SavedSession {
+            type_: "saved".to_string(),
+            name: format!("{} ({})", session.name, i + 2),
+            generation_date_time: session.generation_date_time,
+            creation_date_time: now,
+            modification_date_time: now,
+            tags: session.tags.clone(),
+            deleted: "false".to_string(),
+            windows: Json(vec![window]),
+            ..Default::default()
+        };
+        ids.push(
+            insert_saved_session(&mut tx, &new)
+                .await?
+                .last_insert_rowid()
+        );
+    }
+
+    update_saved_session_windows(&mut tx, &session).await?;
+
+    tx.commit().await?;
+
+    Ok(ids)
+}
+
+fn take_tab(session: &mut SavedSession, window: usize, tab: usize) -> Result<Tab, Error> {
+    session
+        .windows
+        .get_mut(window)
+        .and_then(|w| w.tabs.as_mut())
+        .filter(|tabs| tab < tabs.len())
+        .map(|tabs| tabs.remove(tab))
+        .ok_or_else(|| {
+            Error::NotFound(format!(
+                "session {} has no tab {} in window {}",
+                session.id.unwrap_or_default(),
+                tab,
+                window
+            ))
+        })
+}
+
+/// Append a tab to a window, the last one if none is given. A
+/// session without windows gets a new one.
+fn put_tab(session: &mut SavedSession, window: Option<usize>, mut tab: Tab) -> Result<(), Error> {
+    if window.is_none() && session.windows.is_empty() {
+        session.windows.push(Window::default());
+    }
+
+    let last = session.windows.len().saturating_sub(1);
+    let w = window.unwrap_or(last);
+    let window = session.windows.get_mut(w).ok_or_else(|| {
+        Error::NotFound(format!(
+            "session {} has no window {}",
+            session.id.unwrap_or_default(),
+            w
+        ))
+    })?;
+
+    if let Some(id) = window.id {
+        tab.window_id = id;
+    }
+    window.tabs.get_or_insert_with(Vec::new).push(tab);
+
+    Ok(())
+}
+
+/// Move a tab to the end of a window of the same or another session.
+/// Windows left without tabs are dropped, sessions left without
+/// windows are kept.
+pub async fn move_tab(
+    db: &Path,
+    from: i64,
+    window: usize,
+    tab: usize,
+    to: i64,
+    to_window: Option<usize>
+) -> Result<(), Error> {
+    let mut conn = connect(db).await?;
+    let mut tx = conn.begin().await?;
+
+    let mut source = saved_session(&mut tx, from).await?;
+    let moved = take_tab(&mut source, window, tab)?;
+
+    if from == to {
+        put_tab(&mut source, to_window, moved)?;
+    } else {
+        let mut target = saved_session(&mut tx, to).await?;
+        put_tab(&mut target, to_window, moved)?;
+        target.reindex_tabs();
+        update_saved_session_windows(&mut tx, &target).await?;
+    }
+
+    source.reindex_tabs();
+    update_saved_session_windows(&mut tx, &source).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Flag sessions as deleted, like the extension does, or delete them
+/// permanently. Fails without changing anything if one of them
+/// doesn't exist.
+pub async fn delete(db: &Path, ids: &[i64], purge: bool) -> Result<(), Error> {
+    let mut conn = connect(db).await?;
+    let mut tx = conn.begin().await?;
+
+    for id in ids.iter() {
+        let session = saved_session(&mut tx, *id).await?;
+        if purge {
+            delete_saved_session(&mut tx, session.id.unwrap_or_default()).await?;
+        } else {
+            flag_saved_session_deleted(&mut tx, *id).await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use sqlx::types::Json;
+    use tempfile::TempDir;
+
+    use super::{delete, merge, move_tab, rename, split};
+    use crate::chrome::tab::Tab;
+    use crate::chrome::window::Window;
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::session_buddy::database::{connect, insert_saved_session, saved_session};
+    use crate::session_buddy::session::SavedSession;
+
+    fn window(urls: &[&str]) -> Window {
+        Window {
+            tabs: Some(
+                urls.iter()
+                    .enumerate()
+                    .map(|(i, url)| Tab {
+                        index: i as i64,
+                        url: Some(url.to_string()),
+                        ..Default::default()
+                    })
+                    .collect()
+            ),
+            ..Default::default()
+        }
+    }
+
+    /// A database with a session of two windows and one of one.
+    async fn database() -> (TempDir, PathBuf) {
+        let (dir, db) = scratch_db().await;
+        let mut conn = connect(&db).await.unwrap();
+        for (name, windows) in [
+            ("a", vec![window(&["a1", "a2"]), window(&["a3"])]),
+            ("b", vec![window(&["b1"])])
+        ] {
+            let session = SavedSession {
+                name: name.to_string(),
+                tags: format!(r#"["{}"]"#, name),
+                deleted: "false".to_string(),
+                windows: Json(windows),
+                ..Default::default()
+            };
+            insert_saved_session(&mut conn, &session).await.unwrap();
+        }
+
+        (dir, db)
+    }
+
+    async fn session(db: &Path, id: i64) -> SavedSession {
+        saved_session(&mut connect(db).await.unwrap(), id)
+            .await
+            .unwrap()
+    }
+
+    fn urls(session: &SavedSession) -> Vec<Vec<String>> {
+        session
+            .windows
+            .iter()
+            .map(|w| {
+                w.tabs
+                    .iter()
+                    .flatten()
+                    .filter_map(|t| t.url.clone())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn renames_merges_and_deletes() {
+        let (_dir, db) = database().await;
+        let before = session(&db, 1).await.modification_date_time;
+
+        rename(&db, 1, "c").await.unwrap();
+        assert_eq!(merge(&db, &[1, 2, 2, 1], None).await.unwrap(), 2);
+
+        let merged = session(&db, 1).await;
+        assert_eq!(merged.name, "c");
+        assert_eq!(merged.tag_list(), ["a", "b"]);
+        assert_eq!(urls(&merged), [vec!["a1", "a2"], vec!["a3"], vec!["b1"]]);
+        assert_eq!(merged.unfiltered_tab_count, 4);
+        assert!(merged.modification_date_time > before);
+        assert!(session(&db, 2).await.is_deleted());
+
+        assert!(rename(&db, 3, "x").await.is_err());
+        assert!(delete(&db, &[1, 3], true).await.is_err());
+        delete(&db, &[1], false).await.unwrap();
+        assert!(session(&db, 1).await.is_deleted());
+        delete(&db, &[1, 2], true).await.unwrap();
+        assert!(saved_session(&mut connect(&db).await.unwrap(), 1)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn splits_and_moves_tabs() {
+        let (_dir, db) = database().await;
+
+        assert_eq!(split(&db, 1).await.unwrap(), [3]);
+        assert_eq!(split(&db, 2).await.unwrap(), Vec::<i64>::new());
+        let new = session(&db, 3).await;
+        assert_eq!(new.name, "a (2)");
+        assert_eq!(new.tag_list(), ["a"]);
+        assert_eq!(urls(&new), [vec!["a3"]]);
+        assert_eq!(session(&db, 1).await.unfiltered_window_count, 1);
+
+        move_tab(&db, 1, 0, 0, 2, None).await.unwrap();
+        move_tab(&db, 3, 0, 0, 1, Some(0)).await.unwrap();
+        assert!(move_tab(&db, 1, 0, 5, 2, None).await.is_err());
+
+        let a = session(&db, 1).await;
+        assert_eq!(urls(&a), [vec!["a2", "a3"]]);
+        assert_eq!(a.windows[0].tabs.as_ref().unwrap()[1].index, 1);
+        assert_eq!(urls(&session(&db, 2).await), [vec!["b1", "a1"]]);
+        let emptied = session(&db, 3).await;
+        assert_eq!(emptied.unfiltered_window_count, 0);
+        assert_eq!(emptied.unfiltered_tab_count, 0);
+    }
+}
@@ -0,0 +1,70 @@
+use crate::chrome::tab::Tab;
+use crate::chrome::window::Window;
+use crate::session_buddy::query::{Context, Expr};
+use crate::session_buddy::session::{PreviousSession, SavedSession};
+
+/// Which sessions to include with regard to their deleted flag.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum Deleted {
+    /// Skip sessions flagged as deleted.
+    #[default]
+    Exclude,
+    /// Include all sessions.
+    Include,
+    /// Only include sessions flagged as deleted.
+    Only
+}
+
+/// Selects the sessions read commands operate on.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub deleted: Deleted,
+    /// Expression sessions and tabs have to match, see
+    /// [`crate::session_buddy::query`].
+    pub expr: Option<Expr>
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Filter {
+            ..Default::default()
+        }
+    }
+
+    pub fn matches(&self, session: &SavedSession) -> bool {
+        self.matches_deleted(session.is_deleted()) && self.matches_expr(session)
+    }
+
+    /// Whether a session matches the expression, whatever its
+    /// deleted flag.
+    pub fn matches_expr(&self, session: &SavedSession) -> bool {
+        self.expr
+            .as_ref()
+            .is_none_or(|e| e.matches_session(session))
+    }
+
+    /// Like [`Filter::matches`], for a previous session.
+    pub fn matches_previous(&self, session: &PreviousSession) -> bool {
+        self.matches_deleted(session.is_deleted()) && self.matches_expr(&session.to_saved_session())
+    }
+
+    /// Whether a tab of an included session is included.
+    pub fn matches_tab(&self, session: &SavedSession, window: &Window, tab: &Tab) -> bool {
+        self.expr.as_ref().is_none_or(|e| {
+            e.eval(&Context {
+                session,
+                window: Some(window),
+                tab: Some(tab)
+            })
+        })
+    }
+
+    /// Whether a session with the given deleted flag is included.
+    pub fn matches_deleted(&self, deleted: bool) -> bool {
+        match self.deleted {
+            Deleted::Exclude => !deleted,
+            Deleted::Include => true,
+            Deleted::Only => deleted
+        }
+    }
+}
@@ -0,0 +1,506 @@
+//! Removing every trace of a site: its tabs in saved and previous
+//! sessions and in the undo history, and the thumbnails of sessions
+//! that showed it.
+
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+use sqlx::types::JsonValue;
+use sqlx::{Connection, SqliteConnection};
+use url::Url;
+
+use crate::chrome::tab::Tab;
+use crate::session_buddy::database::{
+    connect, delete_saved_session, previous_sessions, saved_sessions, update_saved_session_windows
+};
+use crate::session_buddy::index;
+use crate::session_buddy::session::PreviousSession;
+use crate::session_buddy::undo::{undo_entries, Undo};
+use crate::Error;
+
+/// What to forget.
+#[derive(Debug, Clone)]
+pub enum Site {
+    /// A domain and all its subdomains.
+    Domain(String),
+    /// URLs matching a regular expression.
+    Regex(Regex)
+}
+
+impl Site {
+    /// A pattern of only letters, digits, hyphens and dots, with at
+    /// least one dot, is a domain, anything else a regex.
+    pub fn new(pattern: &str) -> Result<Self, Error> {
+        let is_domain = pattern.contains('.')
+            && pattern
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+
+        if is_domain {
+            Ok(Site::Domain(pattern.trim_matches('.').to_ascii_lowercase()))
+        } else {
+            Ok(Site::Regex(
+                Regex::new(pattern).map_err(|e| Error::Pattern(e.to_string()))?
+            ))
+        }
+    }
+
+    pub fn is_match(&self, url: &str) -> bool {
+        match self {
+            Site::Domain(domain) => Url::parse(url)
+                .ok()
+                .and_then(|u| u.host_str().map(|h| h.trim_end_matches('.').to_lowercase()))
+                .is_some_and(|host| {
+                    host == *domain
+                        || host
+                            .strip_suffix(domain.as_str())
+                            .is_some_and(|sub| sub.ends_with('.'))
+                }),
+            Site::Regex(regex) => regex.is_match(url)
+        }
+    }
+
+    /// Whether the URL or pending URL of a tab matches.
+    pub fn matches_tab(&self, tab: &Tab) -> bool {
+        [&tab.url, &tab.pending_url]
+            .into_iter()
+            .flatten()
+            .any(|url| self.is_match(url))
+    }
+}
+
+/// What forgetting a site did to a database.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct ForgetReport {
+    /// Number of tabs removed from saved and previous sessions.
+    pub tabs: usize,
+    /// Number of sessions tabs were removed from.
+    pub sessions: usize,
+    /// Number of sessions deleted because no tabs were left.
+    pub deleted_sessions: usize,
+    /// Number of thumbnails removed from sessions that were kept.
+    pub thumbnails: usize,
+    /// Number of tabs removed from the undo history.
+    pub undo_tabs: usize,
+    /// Number of undo entries changed or, when nothing was left,
+    /// deleted.
+    pub undo_entries: usize,
+    /// Size of the database file before.
+    pub size_before: u64,
+    /// Size of the database file after VACUUM.
+    pub size_after: u64,
+    /// Whether the search index next to the database was removed.
+    pub index_removed: bool
+}
+
+/// Remove matching tabs from the windows of a register. Windows
+/// left without tabs are dropped.
+fn forget_windows(site: &Site, windows: &mut Vec<JsonValue>) -> usize {
+    let mut removed = 0;
+
+    windows.retain_mut(|window| {
+        let Some(tabs) = window.get_mut("tabs").and_then(|t| t.as_array_mut()) else {
+            return true;
+        };
+        let before = tabs.len();
+        tabs.retain(|tab| !matches_json_tab(site, tab));
+        removed += before - tabs.len();
+        before == tabs.len() || !tabs.is_empty()
+    });
+
+    removed
+}
+
+/// Like [`Site::matches_tab`], for tabs that may not deserialize.
+fn matches_json_tab(site: &Site, tab: &JsonValue) -> bool {
+    ["url", "pendingUrl"]
+        .into_iter()
+        .filter_map(|key| tab.get(key)?.as_str())
+        .any(|url| site.is_match(url))
+}
+
+/// Remove matching tabs from an undo register holding a session, a
+/// list of windows, a window or a tab. Returns the number of removed
+/// tabs and whether the register is left without any.
+fn forget_register(site: &Site, register: &mut JsonValue) -> (usize, bool) {
+    if let Some(windows) = register.as_array_mut() {
+        let removed = forget_windows(site, windows);
+        return (removed, windows.is_empty());
+    }
+
+    let Some(object) = register.as_object_mut() else {
+        return (0, false);
+    };
+
+    if let Some(windows) = object.get_mut("windows") {
+        // Windows of sessions may be serialized into a string, just
+        // like in the database.
+        let (removed, empty) = match windows {
+            JsonValue::String(s) => {
+                let Ok(mut list) = serde_json::from_str::<Vec<JsonValue>>(s) else {
+                    return (0, false);
+                };
+                let removed = forget_windows(site, &mut list);
+                if removed > 0 {
+                    *s = JsonValue::from(list.clone()).to_string();
+                }
+                (removed, list.is_empty())
+            }
+            JsonValue::Array(list) => {
+                let removed = forget_windows(site, list);
+                (removed, list.is_empty())
+            }
+            _ => (0, false)
+        };
+        if removed > 0 {
+            object.remove("thumbnail");
+        }
+        return (removed, empty);
+    }
+
+    if object.contains_key("tabs") {
+        let mut windows = vec![register.take()];
+        let removed = forget_windows(site, &mut windows);
+        return match windows.pop() {
+            Some(window) => {
+                *register = window;
+                (removed, false)
+            }
+            None => (removed, true)
+        };
+    }
+
+    if object.contains_key("url") && matches_json_tab(site, register) {
+        return (1, true);
+    }
+
+    (0, false)
+}
+
+async fn forget_previous_sessions(
+    conn: &mut SqliteConnection,
+    site: &Site,
+    sessions: Vec<PreviousSession>,
+    report: &mut ForgetReport
+) -> Result<(), Error> {
+    for mut session in sessions {
+        let removed = session.retain_tabs(|t| !site.matches_tab(t));
+        if removed == 0 {
+            continue;
+        }
+
+        report.tabs += removed;
+
+        if session.windows.is_empty() {
+            sqlx::query("DELETE FROM PreviousSessions WHERE id = ?")
+                .bind(session.id)
+                .execute(&mut *conn)
+                .await?;
+            report.deleted_sessions += 1;
+        } else {
+            sqlx::query(
+                r#"
+                UPDATE PreviousSessions SET
+                    thumbnail = NULL,
+                    windows = $1,
+                    unfilteredWindowCount = $2,
+                    filteredWindowCount = $2,
+                    unfilteredTabCount = $3,
+                    filteredTabCount = $3
+                WHERE id = $4
+                "#
+            )
+            .bind(&session.windows)
+            .bind(session.count_windows())
+            .bind(session.count_tabs())
+            .bind(session.id)
+            .execute(&mut *conn)
+            .await?;
+            report.thumbnails += usize::from(session.thumbnail.is_some());
+            report.sessions += 1;
+        }
+    }
+
+    Ok(())
+}
+
+async fn forget_undo(
+    conn: &mut SqliteConnection,
+    site: &Site,
+    entries: Vec<Undo>,
+    report: &mut ForgetReport
+) -> Result<(), Error> {
+    for undo in entries {
+        let mut registers = [
+            undo.register1,
+            undo.register2,
+            undo.register3,
+            undo.register4,
+            undo.register5
+        ];
+        let mut removed = 0;
+        let mut emptied = false;
+
+        for register in registers.iter_mut().flatten() {
+            let Ok(mut value) = serde_json::from_str::<JsonValue>(register) else {
+                continue;
+            };
+            let (r, empty) = forget_register(site, &mut value);
+            if r > 0 {
+                removed += r;
+                emptied |= empty;
+                *register = value.to_string();
+            }
+        }
+
+        if removed == 0 {
+            continue;
+        }
+
+        report.undo_tabs += removed;
+        report.undo_entries += 1;
+
+        if emptied {
+            sqlx::query("DELETE FROM Undo WHERE id = ?")
+                .bind(undo.id)
+                .execute(&mut *conn)
+                .await?;
+        } else {
+            let [r1, r2, r3, r4, r5] = registers;
+            sqlx::query(
+                r#"
+                UPDATE Undo SET
+                    register1 = $1,
+                    register2 = $2,
+                    register3 = $3,
+                    register4 = $4,
+                    register5 = $5
+                WHERE id = $6
+                "#
+            )
+            .bind(r1)
+            .bind(r2)
+            .bind(r3)
+            .bind(r4)
+            .bind(r5)
+            .bind(undo.id)
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove all tabs of a site from saved sessions, previous sessions
+/// and the undo history in one transaction, deleted sessions
+/// included. Windows and sessions left without tabs are deleted,
+/// undo entries left without anything to restore too. Sessions that
+/// had such tabs lose their thumbnail, which may show the site.
+/// Afterwards the database is VACUUMed, so nothing is left in free
+/// pages, and its search index is removed, as it still has the tabs.
+/// Nothing is written on a dry run.
+pub async fn forget(db: &Path, site: &Site, dry_run: bool) -> Result<ForgetReport, Error> {
+    let mut report = ForgetReport {
+        size_before: fs::metadata(db)?.len(),
+        ..Default::default()
+    };
+
+    let saved = saved_sessions(db).await?;
+    let previous = previous_sessions(db).await?;
+    let undo = undo_entries(db).await?;
+
+    let mut conn = connect(db).await?;
+    let mut tx = conn.begin().await?;
+
+    for mut session in saved {
+        let removed = session.retain_tabs(|t| !site.matches_tab(t));
+        if removed == 0 {
+            continue;
+        }
+
+        report.tabs += removed;
+
+        match (session.windows.is_empty(), session.id) {
+            (true, Some(id)) => {
+                delete_saved_session(&mut tx, id).await?;
+                report.deleted_sessions += 1;
+            }
+            _ => {
+                update_saved_session_windows(&mut tx, &session).await?;
+                report.thumbnails += sqlx::query(
+                    "UPDATE SavedSessions SET thumbnail = NULL WHERE id = ? AND thumbnail IS NOT NULL"
+                )
+                .bind(session.id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected() as usize;
+                report.sessions += 1;
+            }
+        }
+    }
+
+    forget_previous_sessions(&mut tx, site, previous, &mut report).await?;
+    forget_undo(&mut tx, site, undo, &mut report).await?;
+
+    if dry_run {
+        report.size_after = report.size_before;
+        return Ok(report);
+    }
+
+    tx.commit().await?;
+    sqlx::query("VACUUM").execute(&mut conn).await?;
+    drop(conn);
+
+    report.size_after = fs::metadata(db)?.len();
+
+    let index = index::default_path(db);
+    if index.exists() {
+        fs::remove_file(&index)?;
+        report.index_removed = true;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::json;
+    use sqlx::types::Json;
+
+    use super::{forget, forget_register, Site};
+    use crate::chrome::tab::Tab;
+    use crate::chrome::window::Window;
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::session_buddy::database::{
+        connect, insert_saved_session, previous_sessions, saved_sessions
+    };
+    use crate::session_buddy::index;
+    use crate::session_buddy::session::SavedSession;
+    use crate::session_buddy::undo::undo_entries;
+
+    fn window(urls: &[&str]) -> Window {
+        Window {
+            tabs: Some(
+                urls.iter()
+                    .map(|url| Tab {
+                        url: Some(url.to_string()),
+                        ..Default::default()
+                    })
+                    .collect()
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matches_domains_and_regexes() {
+        let site = Site::new("example.com").unwrap();
+        assert!(site.is_match("https://example.com/a"));
+        assert!(site.is_match("http://www.Example.com./"));
+        assert!(!site.is_match("https://notexample.com/"));
+        assert!(!site.is_match("https://other.com/?u=example.com"));
+
+        let site = Site::new("^https://[^/]*tracker").unwrap();
+        assert!(site.is_match("https://ads.tracker.net/"));
+        assert!(Site::new("(").is_err());
+
+        let mut tab = json!({"url": "https://example.com/", "title": "Example"});
+        let site = Site::new("example.com").unwrap();
+        assert_eq!(forget_register(&site, &mut tab), (1, true));
+
+        let mut session = json!({
+            "name": "s",
+            "thumbnail": "data:image/png;base64,",
+            "windows": json!([
+                {"tabs": [{"url": "https://example.com/"}, {"url": "https://rust-lang.org/"}]},
+                {"tabs": [{"url": "https://a.example.com/"}]}
+            ])
+            .to_string()
+        });
+        assert_eq!(forget_register(&site, &mut session), (2, false));
+        assert!(session.get("thumbnail").is_none());
+        assert!(!session["windows"].as_str().unwrap().contains("example.com"));
+    }
+
+    #[tokio::test]
+    async fn forgets_sites_everywhere() {
+        let (_dir, db) = scratch_db().await;
+        let mut conn = connect(&db).await.unwrap();
+        for windows in [
+            vec![window(&["https://example.com/", "https://rust-lang.org/"])],
+            vec![window(&["https://www.example.com/a"])]
+        ] {
+            let session = SavedSession {
+                deleted: "false".to_string(),
+                windows: Json(windows),
+                ..Default::default()
+            };
+            insert_saved_session(&mut conn, &session).await.unwrap();
+        }
+        sqlx::query("UPDATE SavedSessions SET thumbnail = 'data:image/png;base64,'")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let now = Utc::now();
+        sqlx::query(
+            r#"
+            INSERT INTO PreviousSessions (recordingDateTime, creationDateTime, deleted, windows)
+            VALUES ($1, $1, 'false', $2)
+            "#
+        )
+        .bind(now)
+        .bind(Json(vec![
+            window(&["https://example.com/"]),
+            window(&["https://docs.rs/"]),
+        ]))
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO Undo (creationDateTime, register1) VALUES ($1, $2), ($1, $3)")
+            .bind(now)
+            .bind(r#"{"url": "https://example.com/b"}"#)
+            .bind(r#"{"tabs": [{"url": "https://docs.rs/"}]}"#)
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let index = index::default_path(&db);
+        index::build(&db, &index, false).await.unwrap();
+
+        let site = Site::new("example.com").unwrap();
+        let preview = forget(&db, &site, true).await.unwrap();
+        assert_eq!(saved_sessions(&db).await.unwrap().len(), 2);
+        assert!(!preview.index_removed);
+        assert!(index.exists());
+
+        let report = forget(&db, &site, false).await.unwrap();
+        assert_eq!(
+            (report.tabs, report.sessions, report.deleted_sessions),
+            (3, 2, 1)
+        );
+        assert_eq!(
+            (report.thumbnails, report.undo_tabs, report.undo_entries),
+            (1, 1, 1)
+        );
+        assert_eq!(report.tabs, preview.tabs);
+        assert!(report.index_removed);
+        assert!(!index.exists());
+
+        let saved = saved_sessions(&db).await.unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].count_tabs(), 1);
+        let previous = previous_sessions(&db).await.unwrap();
+        assert_eq!(
+            (previous[0].count_windows(), previous[0].count_tabs()),
+            (1, 1)
+        );
+        assert_eq!(undo_entries(&db).await.unwrap().len(), 1);
+
+        let data = std::fs::read(&db).unwrap();
+        assert!(!data.windows(11).any(|w| w == b"example.com"));
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection, Row, SqliteConnection};
+
+use crate::chrome::window::Window;
+use crate::session_buddy::database::{connect, previous_sessions, saved_sessions};
+use crate::session_buddy::filter::{Deleted, Filter};
+use crate::session_buddy::session::{PreviousSession, SavedSession};
+use crate::Error;
+
+/// Schema of the sidecar database. `sessions` remembers a hash of
+/// the content every session was indexed with, so only changed
+/// sessions have to be indexed again. Timestamps aren't enough:
+/// `repair` and `autoname` change saved sessions without touching
+/// their modification time, and `forget` rewrites previous sessions,
+/// which only have the time they were recorded.
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS sessions (
+    kind TEXT NOT NULL,
+    id INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY (kind, id));
+
+CREATE VIRTUAL TABLE IF NOT EXISTS tabs USING fts5(
+    title,
+    url,
+    session_name,
+    window_title,
+    tags,
+    kind UNINDEXED,
+    session_id UNINDEXED,
+    window UNINDEXED,
+    tab UNINDEXED,
+    deleted UNINDEXED,
+    tokenize = 'unicode61');
+"#;
+
+/// What building an index did.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct BuildReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize
+}
+
+/// A tab found in an index, best match first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Found {
+    pub kind: String,
+    pub session_id: i64,
+    pub session_name: String,
+    pub window: i64,
+    pub tab: i64,
+    pub title: String,
+    pub url: String,
+    /// Part of the title, URL or names matching the query, with
+    /// matches in brackets.
+    pub snippet: String,
+    /// BM25 score, lower is better.
+    pub rank: f64
+}
+
+/// Default location of the index of a database: next to it, with
+/// `.index` appended. The extension ignores unknown files.
+pub fn default_path(db: &Path) -> PathBuf {
+    let mut path = db.as_os_str().to_owned();
+    path.push(".index");
+    PathBuf::from(path)
+}
+
+async fn open(index: &Path) -> Result<SqliteConnection, Error> {
+    let mut conn =
+        SqliteConnectOptions::from_str(format!("sqlite://{}", index.display()).as_str())?
+            .log_statements(log::LevelFilter::Trace)
+            .create_if_missing(true)
+            .connect()
+            .await?;
+
+    sqlx::query(SCHEMA).execute(&mut conn).await?;
+
+    Ok(conn)
+}
+
+struct Entry<'a> {
+    kind: &'a str,
+    id: i64,
+    name: &'a str,
+    tags: &'a str,
+    deleted: &'a str,
+    windows: &'a [Window]
+}
+
+async fn insert(conn: &mut SqliteConnection, entry: Entry<'_>) -> Result<(), Error> {
+    for (w, window) in entry.windows.iter().enumerate() {
+        for (t, tab) in window.tabs.iter().flatten().enumerate() {
+            sqlx::query(
+                r#"
+                INSERT INTO tabs (
+                    title, url, session_name, window_title, tags,
+                    kind, session_id, window, tab, deleted
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                "#
+            )
+            .bind(tab.title.as_deref().unwrap_or_default())
+            .bind(tab.url.as_deref().unwrap_or_default())
+            .bind(entry.name)
+            .bind(window.nx_title.as_deref().unwrap_or_default())
+            .bind(entry.tags)
+            .bind(entry.kind)
+            .bind(entry.id)
+            .bind(w as i64)
+            .bind(t as i64)
+            .bind(entry.deleted)
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn remove(conn: &mut SqliteConnection, kind: &str, id: i64) -> Result<(), Error> {
+    sqlx::query("DELETE FROM tabs WHERE kind = $1 AND session_id = $2")
+        .bind(kind)
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("DELETE FROM sessions WHERE kind = $1 AND id = $2")
+        .bind(kind)
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Hex SHA-256 of what gets indexed of a session.
+fn content_hash(name: &str, tags: &str, deleted: &str, windows: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [name, tags, deleted, windows] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Index the saved and previous sessions of a database. Only
+/// sessions whose content changed since the last build are indexed
+/// again, unless rebuild is set.
+pub async fn build(db: &Path, index: &Path, rebuild: bool) -> Result<BuildReport, Error> {
+    let mut source = connect(db).await?;
+    let mut conn = open(index).await?;
+    let mut report = BuildReport::default();
+
+    let mut tx = conn.begin().await?;
+
+    if rebuild {
+        sqlx::query("DELETE FROM tabs").execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM sessions")
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let mut indexed: HashMap<(String, i64), String> =
+        sqlx::query_as::<_, (String, i64, String)>("SELECT kind, id, hash FROM sessions")
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|(kind, id, hash)| ((kind, id), hash))
+            .collect();
+
+    let current: Vec<(String, i64, String, String, String, String)> = sqlx::query_as(
+        r#"
+        SELECT 'saved', id, COALESCE(name, ''), COALESCE(tags, ''),
+            COALESCE(deleted, ''), COALESCE(windows, '')
+        FROM SavedSessions
+        UNION ALL
+        SELECT 'previous', id, '', '', COALESCE(deleted, ''), COALESCE(windows, '')
+        FROM PreviousSessions
+        "#
+    )
+    .fetch_all(&mut source)
+    .await?;
+
+    for (kind, id, name, tags, deleted, windows) in current {
+        let hash = content_hash(&name, &tags, &deleted, &windows);
+
+        match indexed.remove(&(kind.clone(), id)) {
+            Some(h) if h == hash => {
+                report.unchanged += 1;
+                continue;
+            }
+            Some(_) => {
+                remove(&mut tx, &kind, id).await?;
+                report.updated += 1;
+            }
+            None => report.added += 1
+        }
+
+        if kind == "saved" {
+            let session: SavedSession = sqlx::query_as("SELECT * FROM SavedSessions WHERE id = ?")
+                .bind(id)
+                .fetch_one(&mut source)
+                .await?;
+            insert(
+                &mut tx,
+                Entry {
+                    kind: &kind,
+                    id,
+                    name: &session.name,
+                    tags: &session.tag_list().join(", "),
+                    deleted: &session.deleted,
+                    windows: &session.windows
+                }
+            )
+            .await?;
+        } else {
+            let session: PreviousSession =
+                sqlx::query_as("SELECT * FROM PreviousSessions WHERE id = ?")
+                    .bind(id)
+                    .fetch_one(&mut source)
+                    .await?;
+            insert(
+                &mut tx,
+                Entry {
+                    kind: &kind,
+                    id,
+                    name: "",
+                    tags: "",
+                    deleted: &session.deleted,
+                    windows: &session.windows
+                }
+            )
+            .await?;
+        }
+
+        sqlx::query("INSERT INTO sessions (kind, id, hash) VALUES ($1, $2, $3)")
+            .bind(&kind)
+            .bind(id)
+            .bind(&hash)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // Whatever is left was deleted from the database.
+    for (kind, id) in indexed.into_keys() {
+        remove(&mut tx, &kind, id).await?;
+        report.removed += 1;
+    }
+
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+/// Search an index using FTS5 query syntax, e.g. `rust async`,
+/// `"exact phrase"` or `url:github`. An expression of the filter is
+/// checked against the tabs in db, so tabs the index still has but
+/// db doesn't are left out then.
+pub async fn find(
+    db: &Path,
+    index: &Path,
+    query: &str,
+    limit: u32,
+    filter: &Filter
+) -> Result<Vec<Found>, Error> {
+    if !index.exists() {
+        return Err(Error::NotFound(format!(
+            "{} doesn't exist, run `sbh index build` first",
+            index.display()
+        )));
+    }
+
+    let mut conn = open(index).await?;
+
+    let deleted = match filter.deleted {
+        Deleted::Exclude => "AND deleted != 'true'",
+        Deleted::Include => "",
+        Deleted::Only => "AND deleted = 'true'"
+    };
+
+    let rows = sqlx::query(&format!(
+        r#"
+        SELECT kind, session_id, session_name, window, tab, title, url,
+            snippet(tabs, -1, '[', ']', '…', 10) AS snippet,
+            bm25(tabs) AS rank
+        FROM tabs WHERE tabs MATCH $1 {}
+        ORDER BY rank LIMIT $2
+        "#,
+        deleted
+    ))
+    .bind(query)
+    // Without a limit, -1, when the expression decides what is left.
+    .bind(match filter.expr {
+        Some(_) => -1,
+        None => i64::from(limit)
+    })
+    .fetch_all(&mut conn)
+    .await
+    .map_err(|e| match e {
+        // The schema is known, so a generic error can only come from a
+        // query FTS5 doesn't understand.
+        sqlx::Error::Database(e) if e.code().as_deref() == Some("1") => {
+            Error::Pattern(e.message().to_string())
+        }
+        e => e.into()
+    })?;
+
+    let mut found = rows
+        .iter()
+        .map(|row| {
+            Ok(Found {
+                kind: row.try_get("kind")?,
+                session_id: row.try_get("session_id")?,
+                session_name: row.try_get("session_name")?,
+                window: row.try_get("window")?,
+                tab: row.try_get("tab")?,
+                title: row.try_get("title")?,
+                url: row.try_get("url")?,
+                snippet: row.try_get("snippet")?,
+                rank: row.try_get("rank")?
+            })
+        })
+        .collect::<Result<Vec<Found>, Error>>()?;
+
+    if filter.expr.is_some() {
+        let mut sessions: HashMap<(&str, i64), SavedSession> = HashMap::new();
+        for session in saved_sessions(db).await? {
+            if let Some(id) = session.id {
+                sessions.insert(("saved", id.into()), session);
+            }
+        }
+        for session in previous_sessions(db).await? {
+            sessions.insert(("previous", session.id.into()), session.to_saved_session());
+        }
+
+        found.retain(|f| {
+            let Some(session) = sessions.get(&(f.kind.as_str(), f.session_id)) else {
+                return false;
+            };
+            let window = session.windows.get(f.window as usize);
+            let tab = window.and_then(|w| w.tabs.as_ref()?.get(f.tab as usize));
+            match (window, tab) {
+                (Some(window), Some(tab)) => filter.matches_tab(session, window, tab),
+                _ => false
+            }
+        });
+        found.truncate(limit as usize);
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::types::Json;
+
+    use super::{build, default_path, find};
+    use crate::chrome::tab::Tab;
+    use crate::chrome::window::Window;
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::session_buddy::database::{connect, insert_saved_session};
+    use crate::session_buddy::filter::Filter;
+    use crate::session_buddy::query::Expr;
+    use crate::session_buddy::session::SavedSession;
+
+    #[tokio::test]
+    async fn builds_incrementally_and_finds_tabs() {
+        let (_dir, db) = scratch_db().await;
+        let index = default_path(&db);
+
+        let session = SavedSession {
+            name: "Reading".to_string(),
+            deleted: "false".to_string(),
+            windows: Json(vec![Window {
+                tabs: Some(vec![Tab {
+                    title: Some("Asynchronous Programming in Rust".to_string()),
+                    url: Some("https://rust-lang.github.io/async-book/".to_string()),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        insert_saved_session(&mut connect(&db).await.unwrap(), &session)
+            .await
+            .unwrap();
+
+        assert_eq!(build(&db, &index, false).await.unwrap().added, 1);
+        assert_eq!(build(&db, &index, false).await.unwrap().unchanged, 1);
+
+        // Changes which don't touch the modification time, like those
+        // of repair or autoname, are picked up too.
+        sqlx::query("UPDATE SavedSessions SET name = 'Async reading'")
+            .execute(&mut connect(&db).await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(build(&db, &index, false).await.unwrap().updated, 1);
+        assert_eq!(build(&db, &index, false).await.unwrap().unchanged, 1);
+
+        let found = find(&db, &index, "rust async*", 10, &Filter::new())
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].session_name, "Async reading");
+    }
+
+    #[tokio::test]
+    async fn finds_tabs_matching_expressions() {
+        let (_dir, db) = scratch_db().await;
+        let index = default_path(&db);
+
+        let tab = |url: &str, pinned| Tab {
+            title: Some("Rust".to_string()),
+            url: Some(url.to_string()),
+            pinned,
+            ..Default::default()
+        };
+        let session = SavedSession {
+            deleted: "false".to_string(),
+            windows: Json(vec![Window {
+                tabs: Some(vec![
+                    tab("https://www.rust-lang.org/", false),
+                    tab("https://doc.rust-lang.org/", true),
+                    tab("https://docs.rs/", false),
+                ]),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        insert_saved_session(&mut connect(&db).await.unwrap(), &session)
+            .await
+            .unwrap();
+        build(&db, &index, false).await.unwrap();
+
+        let filter = |expr| Filter {
+            expr: Some(Expr::parse(expr).unwrap()),
+            ..Default::default()
+        };
+        let found = find(&db, &index, "rust", 10, &filter("pinned"))
+            .await
+            .unwrap();
+        let urls: Vec<&str> = found.iter().map(|f| f.url.as_str()).collect();
+        assert_eq!(urls, ["https://doc.rust-lang.org/"]);
+
+        let found = find(&db, &index, "rust", 1, &filter("NOT pinned"))
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_ne!(found[0].url, "https://doc.rust-lang.org/");
+    }
+}
@@ -0,0 +1,301 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::chrome::window::Window;
+use crate::session_buddy::database::{collect_saved_sessions, previous_sessions, saved_sessions};
+use crate::session_buddy::filter::Filter;
+use crate::session_buddy::normalize::Rules;
+use crate::Error;
+
+/// Header every SQLite database file starts with.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// How a pattern is matched against URLs.
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum MatchMode {
+    /// The whole URL
+    #[default]
+    Exact,
+    /// The beginning of the URL
+    Prefix,
+    /// Shell glob, `*` also matches `/`
+    Glob,
+    /// Regular expression, anywhere in the URL
+    Regex
+}
+
+/// A compiled URL pattern.
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    Exact(String),
+    Prefix(String),
+    /// Like Prefix, but the rest of the URL has to start a new path
+    /// segment, the query or the fragment.
+    PathPrefix(String),
+    Glob(GlobMatcher),
+    Regex(Regex),
+    /// Matches normalized URLs.
+    Normalized(Rules, Box<Matcher>)
+}
+
+impl Matcher {
+    pub fn new(mode: MatchMode, pattern: &str) -> Result<Self, Error> {
+        Ok(match mode {
+            MatchMode::Exact => Matcher::Exact(pattern.to_string()),
+            MatchMode::Prefix => Matcher::Prefix(pattern.to_string()),
+            MatchMode::Glob => Matcher::Glob(
+                Glob::new(pattern)
+                    .map_err(|e| Error::Pattern(e.to_string()))?
+                    .compile_matcher()
+            ),
+            MatchMode::Regex => {
+                Matcher::Regex(Regex::new(pattern).map_err(|e| Error::Pattern(e.to_string()))?)
+            }
+        })
+    }
+
+    /// Normalize URLs and exact or prefix patterns before matching,
+    /// so e.g. tracking parameters don't get in the way. Globs and
+    /// regular expressions are left alone. A prefix losing its
+    /// trailing slash still only matches whole path segments, so
+    /// `https://example.com/` doesn't match `https://example.com.evil.net/`.
+    pub fn normalize(self, rules: &Rules) -> Self {
+        match self {
+            Matcher::Exact(pattern) => Matcher::Normalized(
+                rules.clone(),
+                Box::new(Matcher::Exact(rules.normalize(&pattern)))
+            ),
+            Matcher::Prefix(pattern) => {
+                let normalized = rules.normalize(&pattern);
+                let matcher = if pattern.ends_with('/') && !normalized.ends_with('/') {
+                    Matcher::PathPrefix(normalized)
+                } else {
+                    Matcher::Prefix(normalized)
+                };
+                Matcher::Normalized(rules.clone(), Box::new(matcher))
+            }
+            matcher => matcher
+        }
+    }
+
+    pub fn is_match(&self, url: &str) -> bool {
+        match self {
+            Matcher::Exact(pattern) => url == pattern,
+            Matcher::Prefix(pattern) => url.starts_with(pattern.as_str()),
+            Matcher::PathPrefix(pattern) => url
+                .strip_prefix(pattern.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?', '#'])),
+            Matcher::Glob(glob) => glob.is_match(url),
+            Matcher::Regex(regex) => regex.is_match(url),
+            Matcher::Normalized(rules, matcher) => matcher.is_match(&rules.normalize(url))
+        }
+    }
+}
+
+/// A tab whose URL matched.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Hit {
+    /// Database or backup file.
+    pub source: PathBuf,
+    /// Type of the session, `saved` or `previous`.
+    pub kind: String,
+    /// Id of the session. Backups of the extension have none, those
+    /// of sbh do.
+    pub session_id: Option<i64>,
+    pub session_name: String,
+    /// Position of the window in the session.
+    pub window: usize,
+    /// Position of the tab in the window.
+    pub tab: usize,
+    pub url: String,
+    pub title: Option<String>,
+    /// Creation time of a saved session, recording time of a
+    /// previous session.
+    pub created: DateTime<Utc>,
+    pub modified: Option<DateTime<Utc>>
+}
+
+/// Whether a file is a SQLite database rather than a backup.
+pub fn is_database(path: &Path) -> Result<bool, Error> {
+    let mut header = [0; 16];
+    match File::open(path)?.read_exact(&mut header) {
+        Ok(()) => Ok(&header == SQLITE_HEADER),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into())
+    }
+}
+
+fn find(
+    hits: &mut Vec<Hit>,
+    matcher: &Matcher,
+    windows: &[Window],
+    session: impl Fn(usize, usize, &str, Option<&str>) -> Hit
+) {
+    for (w, window) in windows.iter().enumerate() {
+        for (t, tab) in window.tabs.iter().flatten().enumerate() {
+            if let Some(url) = tab.url.as_deref().filter(|url| matcher.is_match(url)) {
+                hits.push(session(w, t, url, tab.title.as_deref()));
+            }
+        }
+    }
+}
+
+/// Find all tabs with a matching URL in a database or backup file.
+pub async fn lookup(source: &Path, matcher: &Matcher, filter: &Filter) -> Result<Vec<Hit>, Error> {
+    let mut hits = vec![];
+
+    let hit = |kind: &str, id, name: &str, created, modified| {
+        let source = source.to_path_buf();
+        let kind = kind.to_string();
+        let name = name.to_string();
+        move |window, tab, url: &str, title: Option<&str>| Hit {
+            source: source.clone(),
+            kind: kind.clone(),
+            session_id: id,
+            session_name: name.clone(),
+            window,
+            tab,
+            url: url.to_string(),
+            title: title.map(String::from),
+            created,
+            modified
+        }
+    };
+
+    if is_database(source)? {
+        for session in saved_sessions(source)
+            .await?
+            .iter()
+            .filter(|s| filter.matches(s))
+        {
+            find(
+                &mut hits,
+                matcher,
+                &session.windows,
+                hit(
+                    "saved",
+                    session.id.map(i64::from),
+                    &session.name,
+                    session.creation_date_time,
+                    Some(session.modification_date_time)
+                )
+            );
+        }
+
+        for session in previous_sessions(source)
+            .await?
+            .iter()
+            .filter(|s| filter.matches_previous(s))
+        {
+            find(
+                &mut hits,
+                matcher,
+                &session.windows,
+                hit(
+                    "previous",
+                    Some(session.id.into()),
+                    "",
+                    session.recording_date_time,
+                    None
+                )
+            );
+        }
+    } else {
+        for session in collect_saved_sessions(&[source.to_path_buf()])
+            .await?
+            .iter()
+            .filter(|s| filter.matches(s))
+        {
+            find(
+                &mut hits,
+                matcher,
+                &session.windows,
+                hit(
+                    match session.type_.as_str() {
+                        "" => "saved",
+                        t => t
+                    },
+                    session.id.map(i64::from),
+                    &session.name,
+                    session.creation_date_time,
+                    Some(session.modification_date_time)
+                )
+            );
+        }
+    }
+
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup, MatchMode, Matcher};
+    use crate::session_buddy::filter::Filter;
+    use crate::session_buddy::normalize::Rules;
+
+    #[test]
+    fn matches_urls() {
+        let url = "https://github.com/rxw1/sbh/issues/12";
+
+        let matches = |mode, pattern| Matcher::new(mode, pattern).unwrap().is_match(url);
+
+        assert!(matches(MatchMode::Exact, url));
+        assert!(!matches(MatchMode::Exact, "https://github.com/"));
+        assert!(matches(MatchMode::Prefix, "https://github.com/rxw1/"));
+        assert!(matches(MatchMode::Glob, "https://github.com/*/issues/*"));
+        assert!(!matches(MatchMode::Glob, "https://gitlab.com/*"));
+        assert!(matches(MatchMode::Regex, r"issues/\d+$"));
+
+        assert!(Matcher::new(MatchMode::Regex, "(").is_err());
+
+        let normalized = Matcher::new(MatchMode::Exact, "http://github.com/rxw1/sbh/issues/12/")
+            .unwrap()
+            .normalize(&Rules::canonical());
+        assert!(normalized.is_match("https://www.github.com/rxw1/sbh/issues/12?utm_source=x"));
+
+        let prefix = |pattern| {
+            Matcher::new(MatchMode::Prefix, pattern)
+                .unwrap()
+                .normalize(&Rules::canonical())
+        };
+        let rust_lang = prefix("https://github.com/rust-lang/");
+        assert!(rust_lang.is_match("http://www.github.com/rust-lang/rust"));
+        assert!(rust_lang.is_match("https://github.com/rust-lang/"));
+        assert!(!rust_lang.is_match("https://github.com/rust-lang-nursery/"));
+        let root = prefix("https://example.com/");
+        assert!(root.is_match("https://example.com"));
+        assert!(root.is_match("https://example.com/a?b=c"));
+        assert!(!root.is_match("https://example.com.evil.net/"));
+        assert!(
+            prefix("https://github.com/rust-").is_match("https://github.com/rust-lang-nursery/")
+        );
+    }
+
+    #[tokio::test]
+    async fn finds_tabs_in_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = dir.path().join("backup.json");
+        std::fs::write(
+            &backup,
+            r#"{"sessions": [
+                {"type": "saved", "id": 7, "name": "s", "windows": [
+                    {"tabs": [{"url": "https://a.com/"}, {"url": "https://b.com/"}]}
+                ]},
+                {"type": "saved", "windows": [{"tabs": [{"url": "https://b.com/"}]}]}
+            ]}"#
+        )
+        .unwrap();
+
+        let matcher = Matcher::new(MatchMode::Exact, "https://b.com/").unwrap();
+        let hits = lookup(&backup, &matcher, &Filter::new()).await.unwrap();
+        let found: Vec<_> = hits.iter().map(|h| (h.session_id, h.tab)).collect();
+        assert_eq!(found, [(Some(7), 1), (None, 0)]);
+    }
+}
@@ -1,8 +1,28 @@
+pub mod analyze;
+pub mod autoname;
 pub mod backup;
 pub mod database;
+pub mod edit;
+pub mod filter;
+pub mod forget;
+pub mod index;
+pub mod lookup;
+pub mod normalize;
+pub mod previous;
+pub mod query;
+pub mod redact;
+pub mod repair;
+pub mod report;
+pub mod rewrite;
 pub mod session;
 pub mod settings;
+pub mod stale;
+pub mod tag;
+pub mod thumbnail;
+pub mod timeline;
+pub mod trash;
 pub mod undo;
+pub mod usage;
 
 pub const SESSION_BUDDY_APPID: &str = "edacconmaakjimmfgnblocblbcdcpbko";
 pub const SESSION_BUDDY_FORMAT: &str = "nxs.json.v1";
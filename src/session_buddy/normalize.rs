@@ -0,0 +1,353 @@
+//! URL normalization. Two rule sets are built in: `tracking` only
+//! removes tracking parameters and is safe to write back,
+//! `canonical` also drops everything that rarely makes a different
+//! page, to tell whether two URLs are the same page. Both can be
+//! changed in the config file, see [`crate::config`].
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Query parameters only there to track clicks. A trailing `*`
+/// matches any suffix.
+pub const TRACKING_PARAMS: [&str; 25] = [
+    "utm_*",
+    "fbclid",
+    "gclid",
+    "dclid",
+    "gbraid",
+    "wbraid",
+    "msclkid",
+    "yclid",
+    "twclid",
+    "ttclid",
+    "igshid",
+    "mc_cid",
+    "mc_eid",
+    "_ga",
+    "_gl",
+    "_hsenc",
+    "_hsmi",
+    "mkt_tok",
+    "li_fat_id",
+    "oly_anon_id",
+    "oly_enc_id",
+    "vero_id",
+    "ref_src",
+    "spm",
+    "s_cid"
+];
+
+/// Query parameters marking AMP variants of a page.
+const AMP_PARAMS: [&str; 3] = ["amp", "amp_js_v", "usqp"];
+
+/// How URLs are normalized. Only http and https URLs are touched.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rules {
+    /// Query parameters to remove, a trailing `*` matches any
+    /// suffix. Case is ignored.
+    pub strip_params: Vec<String>,
+    /// Use https instead of http.
+    pub https: bool,
+    /// Remove a leading `www.` from the host.
+    pub strip_www: bool,
+    /// Remove a trailing slash from the path.
+    pub strip_trailing_slash: bool,
+    /// Remove the fragment.
+    pub strip_fragment: bool,
+    /// Sort the query parameters.
+    pub sort_params: bool,
+    /// Turn AMP URLs into the URL of the regular page, e.g. from the
+    /// Google AMP cache or with an `amp` path segment.
+    pub unwrap_amp: bool
+}
+
+/// Changes to a rule set, e.g. from the config file.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleChanges {
+    /// Query parameters to remove in addition.
+    pub strip_params: Vec<String>,
+    /// Query parameters to keep although the rule set removes them.
+    pub keep_params: Vec<String>,
+    pub https: Option<bool>,
+    pub strip_www: Option<bool>,
+    pub strip_trailing_slash: Option<bool>,
+    pub strip_fragment: Option<bool>,
+    pub sort_params: Option<bool>,
+    pub unwrap_amp: Option<bool>
+}
+
+fn param_matches(pattern: &str, key: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => key
+            .get(..prefix.len())
+            .is_some_and(|k| k.eq_ignore_ascii_case(prefix)),
+        None => key.eq_ignore_ascii_case(pattern)
+    }
+}
+
+impl Rules {
+    /// Only remove tracking parameters.
+    pub fn tracking() -> Self {
+        Rules {
+            strip_params: TRACKING_PARAMS.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Remove tracking parameters and everything else that rarely
+    /// makes a different page.
+    pub fn canonical() -> Self {
+        Rules {
+            https: true,
+            strip_www: true,
+            strip_trailing_slash: true,
+            strip_fragment: true,
+            sort_params: true,
+            unwrap_amp: true,
+            ..Rules::tracking()
+        }
+    }
+
+    /// Apply changes, e.g. from the config file. Parameters to keep
+    /// have to be spelled like in the rule set, e.g. `utm_*`.
+    pub fn extend(mut self, changes: &RuleChanges) -> Self {
+        self.strip_params
+            .extend(changes.strip_params.iter().cloned());
+        self.strip_params.retain(|p| {
+            !changes
+                .keep_params
+                .iter()
+                .any(|k| k.eq_ignore_ascii_case(p))
+        });
+
+        let set = |value: &mut bool, change: Option<bool>| {
+            if let Some(change) = change {
+                *value = change;
+            }
+        };
+        set(&mut self.https, changes.https);
+        set(&mut self.strip_www, changes.strip_www);
+        set(&mut self.strip_trailing_slash, changes.strip_trailing_slash);
+        set(&mut self.strip_fragment, changes.strip_fragment);
+        set(&mut self.sort_params, changes.sort_params);
+        set(&mut self.unwrap_amp, changes.unwrap_amp);
+
+        self
+    }
+
+    fn strips(&self, key: &str) -> bool {
+        self.strip_params.iter().any(|p| param_matches(p, key))
+            || (self.unwrap_amp && AMP_PARAMS.iter().any(|p| key.eq_ignore_ascii_case(p)))
+    }
+
+    /// Normalize a URL. URLs that don't parse or aren't http or
+    /// https are returned as they are.
+    pub fn normalize(&self, url: &str) -> String {
+        let Ok(mut parsed) = Url::parse(url) else {
+            return url.to_string();
+        };
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return url.to_string();
+        }
+        let original = parsed.clone();
+
+        if self.unwrap_amp {
+            if let Some(unwrapped) = unwrap_amp_cache(&parsed) {
+                parsed = unwrapped;
+            }
+            strip_amp_segment(&mut parsed);
+        }
+
+        if self.https && parsed.scheme() == "http" {
+            let _ = parsed.set_scheme("https");
+            if parsed.port() == Some(443) {
+                let _ = parsed.set_port(None);
+            }
+        }
+
+        if self.strip_www {
+            if let Some(host) = parsed.host_str().and_then(|h| h.strip_prefix("www.")) {
+                let host = host.to_string();
+                let _ = parsed.set_host(Some(&host));
+            }
+        }
+
+        if let Some(query) = parsed.query() {
+            // Work on the raw query, so the parameters kept are
+            // written back exactly as they were.
+            let mut params: Vec<&str> = query
+                .split('&')
+                .filter(|p| !p.is_empty())
+                .filter(|p| !self.strips(p.split('=').next().unwrap_or_default()))
+                .collect();
+            if self.sort_params {
+                params.sort_unstable();
+            }
+            let query = params.join("&");
+            parsed.set_query(if query.is_empty() { None } else { Some(&query) });
+        }
+
+        if self.strip_fragment {
+            parsed.set_fragment(None);
+        }
+
+        if self.strip_trailing_slash {
+            let path = parsed.path();
+            if path.len() > 1 && path.ends_with('/') {
+                let path = path.trim_end_matches('/').to_string();
+                parsed.set_path(&path);
+            }
+        }
+
+        // Parsing alone may change a URL, e.g. add a slash after the
+        // host. Only what the rules changed should show.
+        if parsed == original && !self.strip_trailing_slash {
+            return url.to_string();
+        }
+
+        let mut normalized = parsed.to_string();
+        // The root path can't be empty, but the URL can do without it.
+        if self.strip_trailing_slash
+            && parsed.path() == "/"
+            && parsed.query().is_none()
+            && parsed.fragment().is_none()
+        {
+            normalized.pop();
+        }
+        normalized
+    }
+}
+
+/// The original URL of a page served from an AMP cache, e.g.
+/// `https://www.google.com/amp/s/example.com/a` or
+/// `https://example-com.cdn.ampproject.org/c/s/example.com/a`.
+fn unwrap_amp_cache(url: &Url) -> Option<Url> {
+    let host = url.host_str()?;
+    let path = url.path();
+
+    let rest = if host.ends_with(".cdn.ampproject.org") {
+        path.strip_prefix("/c/")
+            .or_else(|| path.strip_prefix("/v/"))?
+    } else if host.trim_start_matches("www.").starts_with("google.") {
+        path.strip_prefix("/amp/")?
+    } else {
+        return None;
+    };
+
+    let original = match rest.strip_prefix("s/") {
+        Some(rest) => format!("https://{}", rest),
+        None => format!("http://{}", rest)
+    };
+    let mut original = Url::parse(&original).ok()?;
+    original.set_query(url.query());
+    Some(original)
+}
+
+/// Remove an `amp` first or last path segment and `.amp` before the
+/// extension of the last one.
+fn strip_amp_segment(url: &mut Url) {
+    let Some(segments) = url.path_segments() else {
+        return;
+    };
+    let mut segments: Vec<String> = segments.map(String::from).collect();
+    let trailing_slash = segments.len() > 1 && segments.last().is_some_and(|s| s.is_empty());
+    if trailing_slash {
+        segments.pop();
+    }
+    let before = segments.clone();
+
+    if segments.len() > 1
+        && segments
+            .last()
+            .is_some_and(|s| s.eq_ignore_ascii_case("amp"))
+    {
+        segments.pop();
+    }
+    if segments.len() > 1 && segments[0].eq_ignore_ascii_case("amp") {
+        segments.remove(0);
+    }
+    if let Some(last) = segments.last_mut() {
+        let lower = last.to_ascii_lowercase();
+        if let Some(i) = lower.find(".amp.") {
+            last.replace_range(i..i + 4, "");
+        } else if lower.ends_with(".amp") {
+            last.truncate(last.len() - 4);
+        }
+    }
+
+    if segments != before {
+        if trailing_slash {
+            segments.push(String::new());
+        }
+        url.set_path(&segments.join("/"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RuleChanges, Rules};
+
+    #[test]
+    fn strips_tracking_parameters() {
+        let rules = Rules::tracking();
+        assert_eq!(
+            rules.normalize("https://example.com/a?utm_source=x&id=1&fbclid=abc&q=a%20b#top"),
+            "https://example.com/a?id=1&q=a%20b#top"
+        );
+        assert_eq!(
+            rules.normalize("http://www.example.com/?gclid=1"),
+            "http://www.example.com/"
+        );
+        assert_eq!(
+            rules.normalize("chrome://newtab/?utm_x=1"),
+            "chrome://newtab/?utm_x=1"
+        );
+        assert_eq!(rules.normalize("not a url"), "not a url");
+        assert_eq!(
+            rules.normalize("https://example.com"),
+            "https://example.com"
+        );
+    }
+
+    #[test]
+    fn canonicalizes_urls() {
+        let rules = Rules::canonical();
+        let same = [
+            "https://example.com/news/story",
+            "http://www.example.com/news/story/",
+            "https://example.com/news/story?utm_medium=social#comments",
+            "https://example.com/news/story/amp",
+            "https://example.com/amp/news/story?amp=1",
+            "https://www.google.com/amp/s/example.com/news/story",
+            "https://example-com.cdn.ampproject.org/c/s/example.com/news/story.amp"
+        ];
+        for url in same {
+            assert_eq!(
+                rules.normalize(url),
+                "https://example.com/news/story",
+                "{}",
+                url
+            );
+        }
+        assert_eq!(
+            rules.normalize("http://example.com/?b=2&a=1"),
+            "https://example.com/?a=1&b=2"
+        );
+        assert_eq!(
+            rules.normalize("https://example.com/"),
+            "https://example.com"
+        );
+
+        let rules = Rules::canonical().extend(&RuleChanges {
+            strip_params: vec!["ref".to_string()],
+            keep_params: vec!["utm_*".to_string()],
+            strip_www: Some(false),
+            ..Default::default()
+        });
+        assert_eq!(
+            rules.normalize("https://www.example.com/?ref=a&utm_source=b"),
+            "https://www.example.com/?utm_source=b"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use crate::session_buddy::database::{connect, insert_saved_session};
+use crate::session_buddy::session::PreviousSession;
+use crate::Error;
+
+/// Get a single PreviousSession by id.
+pub async fn previous_session(db: &Path, id: i64) -> Result<PreviousSession, Error> {
+    let mut conn = connect(db).await?;
+
+    match sqlx::query_as::<_, PreviousSession>("SELECT * FROM PreviousSessions WHERE id = ?")
+        .bind(id)
+        .fetch_one(&mut conn)
+        .await
+    {
+        Ok(session) => Ok(session),
+        Err(sqlx::Error::RowNotFound) => Err(Error::NotFound(format!(
+            "PreviousSessions has no session {}",
+            id
+        ))),
+        Err(e) => Err(e.into())
+    }
+}
+
+/// Copy a PreviousSession into SavedSessions, so it shows up as a
+/// normal saved session in the extension. Returns the id of the new
+/// session.
+pub async fn promote(db: &Path, id: i64) -> Result<i64, Error> {
+    let session = previous_session(db, id).await?.to_saved_session();
+    let mut conn = connect(db).await?;
+
+    Ok(insert_saved_session(&mut conn, &session)
+        .await?
+        .last_insert_rowid())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use sqlx::types::Json;
+
+    use super::{previous_session, promote};
+    use crate::chrome::tab::Tab;
+    use crate::chrome::window::Window;
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::session_buddy::database::{connect, saved_session};
+    use crate::session_buddy::session::SavedSession;
+
+    #[tokio::test]
+    async fn promotes_previous_sessions() {
+        let (_dir, db) = scratch_db().await;
+        let windows = Json(vec![Window {
+            tabs: Some(vec![Tab {
+                url: Some("https://example.com/".to_string()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }]);
+        sqlx::query(
+            r#"
+            INSERT INTO PreviousSessions (recordingDateTime, creationDateTime, deleted,
+                windows, unfilteredWindowCount, unfilteredTabCount)
+            VALUES ($1, $1, 'false', $2, 1, 1)
+            "#
+        )
+        .bind(Utc::now())
+        .bind(&windows)
+        .execute(&mut connect(&db).await.unwrap())
+        .await
+        .unwrap();
+
+        let previous = previous_session(&db, 1).await.unwrap();
+        assert_eq!(previous.windows, windows);
+        assert!(previous_session(&db, 2).await.is_err());
+        assert!(promote(&db, 2).await.is_err());
+
+        let id = promote(&db, 1).await.unwrap();
+        let saved: SavedSession = saved_session(&mut connect(&db).await.unwrap(), id)
+            .await
+            .unwrap();
+        assert!(saved.name.starts_with("Previous session from "));
+        assert_eq!(saved.generation_date_time, previous.recording_date_time);
+        assert_eq!(saved.windows, windows);
+        assert_eq!(saved.unfiltered_tab_count, 1);
+        assert!(!saved.is_deleted());
+        assert!(previous_session(&db, 1).await.is_ok());
+    }
+}
@@ -0,0 +1,582 @@
+//! A small expression language to select sessions and tabs, e.g.
+//!
+//! ```text
+//! domain:github.com AND title~"RFC" AND created>2022-01-01 AND NOT pinned
+//! ```
+//!
+//! Conditions are combined with `AND`, `OR`, `NOT` and parentheses.
+//! Two conditions next to each other mean `AND`. A condition is a
+//! field, an operator and a value, or a boolean field on its own.
+//!
+//! Expressions are evaluated per tab, with the fields of its window
+//! and session. A session matches if any of its tabs does.
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use regex::Regex;
+use url::Url;
+
+use crate::chrome::tab::Tab;
+use crate::chrome::window::Window;
+use crate::session_buddy::session::SavedSession;
+use crate::Error;
+
+/// Fields a condition can test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Name,
+    Tags,
+    Created,
+    Modified,
+    Generated,
+    Deleted,
+    Windows,
+    Tabs,
+    WindowState,
+    WindowType,
+    WindowTitle,
+    WindowFocused,
+    Url,
+    Domain,
+    Title,
+    Pinned,
+    Audible,
+    Muted,
+    Active,
+    Discarded,
+    Incognito
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Text,
+    Date,
+    Number,
+    Bool
+}
+
+impl Field {
+    const ALL: [(&'static str, Field); 21] = [
+        ("name", Field::Name),
+        ("tags", Field::Tags),
+        ("created", Field::Created),
+        ("modified", Field::Modified),
+        ("generated", Field::Generated),
+        ("deleted", Field::Deleted),
+        ("windows", Field::Windows),
+        ("tabs", Field::Tabs),
+        ("window.state", Field::WindowState),
+        ("window.type", Field::WindowType),
+        ("window.title", Field::WindowTitle),
+        ("window.focused", Field::WindowFocused),
+        ("url", Field::Url),
+        ("domain", Field::Domain),
+        ("title", Field::Title),
+        ("pinned", Field::Pinned),
+        ("audible", Field::Audible),
+        ("muted", Field::Muted),
+        ("active", Field::Active),
+        ("discarded", Field::Discarded),
+        ("incognito", Field::Incognito)
+    ];
+
+    fn from_name(name: &str) -> Option<Field> {
+        Field::ALL
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, f)| *f)
+    }
+
+    fn name(&self) -> &'static str {
+        Field::ALL
+            .iter()
+            .find(|(_, f)| f == self)
+            .map(|(n, _)| *n)
+            .unwrap_or_default()
+    }
+
+    fn kind(&self) -> Kind {
+        match self {
+            Field::Name
+            | Field::Tags
+            | Field::WindowState
+            | Field::WindowType
+            | Field::WindowTitle
+            | Field::Url
+            | Field::Domain
+            | Field::Title => Kind::Text,
+            Field::Created | Field::Modified | Field::Generated => Kind::Date,
+            Field::Windows | Field::Tabs => Kind::Number,
+            _ => Kind::Bool
+        }
+    }
+}
+
+/// Comparison of a field and a value. `:` means contains for text,
+/// is a subdomain of for domains, has the tag for tags and equals
+/// for everything else.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Has,
+    Eq,
+    Ne,
+    Matches,
+    Lt,
+    Le,
+    Gt,
+    Ge
+}
+
+impl Op {
+    /// Longest operators first, so `>=` isn't taken for `>`.
+    const ALL: [(&'static str, Op); 8] = [
+        (">=", Op::Ge),
+        ("<=", Op::Le),
+        ("!=", Op::Ne),
+        (":", Op::Has),
+        ("=", Op::Eq),
+        ("~", Op::Matches),
+        (">", Op::Gt),
+        ("<", Op::Lt)
+    ];
+
+    fn compare<T: PartialOrd>(&self, a: T, b: T) -> bool {
+        match self {
+            Op::Has | Op::Eq => a == b,
+            Op::Ne => a != b,
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+            Op::Matches => false
+        }
+    }
+}
+
+/// A regular expression, comparable by its source.
+#[derive(Debug, Clone)]
+pub struct Pattern(pub Regex);
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Regex(Pattern),
+    /// A date without time compares against the day of a timestamp.
+    Day(NaiveDate),
+    Time(DateTime<Utc>),
+    Number(i64),
+    Bool(bool)
+}
+
+/// Parsed expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Condition(Field, Op, Value)
+}
+
+/// A tab with its window and session. Sessions without windows and
+/// windows without tabs are evaluated without them.
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    pub session: &'a SavedSession,
+    pub window: Option<&'a Window>,
+    pub tab: Option<&'a Tab>
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Expr, Error> {
+        let mut parser = Parser {
+            chars: s.chars().collect(),
+            pos: 0
+        };
+        let expr = parser.or()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(expr),
+            Some(c) => Err(parser.error(&format!("unexpected `{}`", c)))
+        }
+    }
+
+    /// Whether any tab of the session matches.
+    pub fn matches_session(&self, session: &SavedSession) -> bool {
+        let context = |window, tab| Context {
+            session,
+            window,
+            tab
+        };
+
+        if session.windows.is_empty() {
+            return self.eval(&context(None, None));
+        }
+
+        session.windows.iter().any(|w| match w.tabs.as_deref() {
+            Some(tabs) if !tabs.is_empty() => {
+                tabs.iter().any(|t| self.eval(&context(Some(w), Some(t))))
+            }
+            _ => self.eval(&context(Some(w), None))
+        })
+    }
+
+    pub fn eval(&self, c: &Context) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(c) && b.eval(c),
+            Expr::Or(a, b) => a.eval(c) || b.eval(c),
+            Expr::Not(a) => !a.eval(c),
+            Expr::Condition(field, op, value) => condition(c, *field, *op, value)
+        }
+    }
+}
+
+fn text<'a>(c: &Context<'a>, field: Field) -> Option<&'a str> {
+    match field {
+        Field::Name => Some(&c.session.name),
+        Field::Tags => Some(&c.session.tags),
+        Field::WindowState => c.window?.state.as_deref(),
+        Field::WindowType => c.window?.type_.as_deref(),
+        Field::WindowTitle => c.window?.nx_title.as_deref(),
+        Field::Url | Field::Domain => c.tab?.url.as_deref(),
+        Field::Title => c.tab?.title.as_deref(),
+        _ => None
+    }
+}
+
+fn boolean(c: &Context, field: Field) -> bool {
+    let tab = |f: fn(&Tab) -> bool| c.tab.is_some_and(f);
+    match field {
+        Field::Deleted => c.session.is_deleted(),
+        Field::WindowFocused => c.window.is_some_and(|w| w.focused),
+        Field::Pinned => tab(|t| t.pinned),
+        Field::Audible => tab(|t| t.audible == Some(true)),
+        Field::Muted => tab(|t| t.muted_info.as_ref().is_some_and(|m| m.muted)),
+        Field::Active => tab(|t| t.active),
+        Field::Discarded => tab(|t| t.discarded),
+        Field::Incognito => c.window.is_some_and(|w| w.incognito) || tab(|t| t.incognito),
+        _ => false
+    }
+}
+
+fn condition(c: &Context, field: Field, op: Op, value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => op.compare(boolean(c, field), *b),
+        Value::Number(n) => {
+            let count = match field {
+                Field::Windows => c.session.count_windows(),
+                _ => c.session.count_tabs()
+            };
+            op.compare(i64::from(count), *n)
+        }
+        Value::Day(_) | Value::Time(_) => {
+            let time = match field {
+                Field::Created => c.session.creation_date_time,
+                Field::Modified => c.session.modification_date_time,
+                _ => c.session.generation_date_time
+            };
+            match value {
+                Value::Day(day) => op.compare(time.date_naive(), *day),
+                Value::Time(t) => op.compare(time, *t),
+                _ => false
+            }
+        }
+        Value::Regex(Pattern(regex)) => text(c, field).is_some_and(|s| regex.is_match(s)),
+        Value::Text(v) => {
+            let Some(s) = text(c, field) else {
+                return op == Op::Ne;
+            };
+            let v = v.to_lowercase();
+
+            if field == Field::Tags {
+                let has = c.session.has_tag(&v);
+                return has != (op == Op::Ne);
+            }
+
+            let s = match field {
+                Field::Domain => match Url::parse(s)
+                    .ok()
+                    .and_then(|u| u.host_str().map(String::from))
+                {
+                    Some(host) => host.to_lowercase(),
+                    None => return op == Op::Ne
+                },
+                _ => s.to_lowercase()
+            };
+
+            match op {
+                Op::Has if field == Field::Domain => s == v || s.ends_with(&format!(".{}", v)),
+                Op::Has => s.contains(&v),
+                Op::Eq => s == v,
+                Op::Ne => s != v,
+                _ => false
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> Error {
+        Error::Pattern(format!("{} at position {}", message, self.pos + 1))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consume a keyword if it is next and stands on its own.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        self.skip_whitespace();
+        let end = self.pos + keyword.len();
+        let matches = self
+            .chars
+            .get(self.pos..end)
+            .is_some_and(|s| s.iter().copied().eq(keyword.chars()))
+            && self
+                .chars
+                .get(end)
+                .is_none_or(|c| c.is_whitespace() || *c == '(' || *c == ')');
+        if matches {
+            self.pos = end;
+        }
+        matches
+    }
+
+    fn or(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.and()?;
+        while self.keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.unary()?;
+        loop {
+            self.skip_whitespace();
+            if self.peek().is_none_or(|c| c == ')') {
+                break;
+            }
+            let start = self.pos;
+            if self.keyword("OR") {
+                self.pos = start;
+                break;
+            }
+            self.keyword("AND");
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        if self.keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+
+        self.skip_whitespace();
+        if self.peek() == Some('(') {
+            self.pos += 1;
+            let expr = self.or()?;
+            self.skip_whitespace();
+            if self.peek() != Some(')') {
+                return Err(self.error("expected `)`"));
+            }
+            self.pos += 1;
+            return Ok(expr);
+        }
+
+        self.condition()
+    }
+
+    fn condition(&mut self) -> Result<Expr, Error> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        if name.is_empty() {
+            return Err(self.error("expected a field"));
+        }
+
+        let field = Field::from_name(&name).ok_or_else(|| {
+            self.pos = start;
+            self.error(&format!("unknown field `{}`", name))
+        })?;
+
+        let op = Op::ALL.iter().find(|(s, _)| {
+            self.chars
+                .get(self.pos..self.pos + s.len())
+                .is_some_and(|c| c.iter().copied().eq(s.chars()))
+        });
+
+        let Some((symbol, op)) = op.copied() else {
+            return match field.kind() {
+                Kind::Bool => Ok(Expr::Condition(field, Op::Eq, Value::Bool(true))),
+                _ => Err(self.error(&format!("`{}` needs an operator and a value", name)))
+            };
+        };
+        self.pos += symbol.len();
+
+        let raw = self.value()?;
+        let invalid = |parser: &Parser, what: &str| {
+            parser.error(&format!("`{}` {} `{}`", field.name(), what, raw))
+        };
+
+        let value = match (field.kind(), op) {
+            (Kind::Text, Op::Matches) => Value::Regex(Pattern(
+                Regex::new(&format!("(?i){}", raw)).map_err(|e| self.error(&e.to_string()))?
+            )),
+            (Kind::Text, Op::Has | Op::Eq | Op::Ne) => Value::Text(raw),
+            (Kind::Bool, Op::Has | Op::Eq | Op::Ne) => match raw.as_str() {
+                "true" | "yes" => Value::Bool(true),
+                "false" | "no" => Value::Bool(false),
+                _ => return Err(invalid(self, "can't be"))
+            },
+            (Kind::Number, op) if op != Op::Matches => {
+                Value::Number(raw.parse().map_err(|_| invalid(self, "can't be"))?)
+            }
+            (Kind::Date, op) if op != Op::Matches => {
+                match NaiveDate::parse_from_str(&raw, "%Y-%m-%d") {
+                    Ok(day) => Value::Day(day),
+                    Err(_) => Value::Time(
+                        DateTime::parse_from_rfc3339(&raw)
+                            .map_err(|_| invalid(self, "can't be"))?
+                            .with_timezone(&Utc)
+                    )
+                }
+            }
+            _ => {
+                return Err(self.error(&format!("`{}` doesn't support `{}`", field.name(), symbol)))
+            }
+        };
+
+        Ok(Expr::Condition(field, op, value))
+    }
+
+    /// A quoted string or everything up to whitespace or `)`.
+    fn value(&mut self) -> Result<String, Error> {
+        let mut value = String::new();
+
+        if self.peek() == Some('"') {
+            self.pos += 1;
+            loop {
+                match self.peek() {
+                    None => return Err(self.error("unterminated string")),
+                    Some('"') => break,
+                    Some('\\') if self.chars.get(self.pos + 1) == Some(&'"') => {
+                        value.push('"');
+                        self.pos += 2;
+                    }
+                    Some(c) => {
+                        value.push(c);
+                        self.pos += 1;
+                    }
+                }
+            }
+            self.pos += 1;
+        } else {
+            while let Some(c) = self.peek().filter(|c| !c.is_whitespace() && *c != ')') {
+                value.push(c);
+                self.pos += 1;
+            }
+            if value.is_empty() {
+                return Err(self.error("expected a value"));
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use sqlx::types::Json;
+
+    use super::Expr;
+    use crate::chrome::tab::Tab;
+    use crate::chrome::window::Window;
+    use crate::session_buddy::session::SavedSession;
+
+    fn session() -> SavedSession {
+        SavedSession {
+            name: "Work".to_string(),
+            tags: "research, ietf".to_string(),
+            creation_date_time: Utc.with_ymd_and_hms(2023, 3, 1, 12, 0, 0).unwrap(),
+            windows: Json(vec![Window {
+                state: Some("maximized".to_string()),
+                tabs: Some(vec![
+                    Tab {
+                        url: Some("https://github.com/rust-lang/rfcs".to_string()),
+                        title: Some("RFCs for changes to Rust".to_string()),
+                        pinned: true,
+                        ..Default::default()
+                    },
+                    Tab {
+                        url: Some("https://docs.github.com/en".to_string()),
+                        title: Some("GitHub Docs".to_string()),
+                        ..Default::default()
+                    },
+                ]),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }
+    }
+
+    fn matches(expr: &str) -> bool {
+        Expr::parse(expr).unwrap().matches_session(&session())
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        assert!(matches(
+            r#"domain:github.com AND title~"RFC" AND created>2022-01-01"#
+        ));
+        assert!(matches("domain:github.com NOT pinned"));
+        assert!(!matches("title~RFC AND NOT pinned"));
+        assert!(matches("tags:IETF window.state=maximized"));
+        assert!(matches("(tabs>5 OR windows=1) AND created=2023-03-01"));
+        assert!(!matches("domain:hub.com OR name!=work"));
+    }
+
+    #[test]
+    fn reports_errors() {
+        for expr in [
+            "",
+            "nope:1",
+            "pinned~x",
+            "created>yesterday",
+            "(pinned",
+            "title"
+        ] {
+            assert!(Expr::parse(expr).is_err(), "{}", expr);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Redacting backups, so they can be shared e.g. for bug reports.
+//! URLs, titles, names and tags are replaced by pseudonyms: keyed
+//! hashes, so the same value always gets the same pseudonym, but
+//! nobody without the key can tell which value it was.
+
+use hmac::{Hmac, Mac};
+use publicsuffix::{List, Psl};
+use sha2::Sha256;
+use url::{Host, Url};
+
+use crate::chrome::tab::Tab;
+use crate::chrome::window::Window;
+use crate::session_buddy::analyze::public_suffix_list;
+use crate::session_buddy::backup::Backup;
+use crate::session_buddy::session::{format_tags, SavedSession};
+use crate::Error;
+
+/// Host of redacted URLs unless the domain structure is kept.
+const REDACTED_HOST: &str = "redacted.invalid";
+
+/// Number of hex digits of a pseudonym.
+const PSEUDONYM_LEN: usize = 16;
+
+/// Replaces values by pseudonyms.
+pub struct Redactor {
+    key: Vec<u8>,
+    /// Public suffix list, to keep the structure of domains, e.g.
+    /// `www.example.co.uk` becomes `1a2b.3c4d.co.uk`.
+    domains: Option<List>
+}
+
+impl Redactor {
+    /// Without a key a random one is used, so pseudonyms only match
+    /// within one backup.
+    pub fn new(key: Option<&str>, keep_domains: bool) -> Result<Self, Error> {
+        Ok(Redactor {
+            key: match key {
+                Some(key) => key.as_bytes().to_vec(),
+                None => rand::random::<[u8; 32]>().to_vec()
+            },
+            domains: if keep_domains {
+                Some(public_suffix_list()?)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Keyed hash of a value. The kind keeps e.g. a title from
+    /// getting the pseudonym of an equal URL.
+    pub fn pseudonym(&self, kind: &str, value: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes keys of any size");
+        mac.update(kind.as_bytes());
+        mac.update(&[0]);
+        mac.update(value.as_bytes());
+
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()[..PSEUDONYM_LEN]
+            .to_string()
+    }
+
+    fn host(&self, host: &str) -> String {
+        let Some(list) = &self.domains else {
+            return REDACTED_HOST.to_string();
+        };
+
+        let suffix = list
+            .suffix(host.as_bytes())
+            .and_then(|s| std::str::from_utf8(s.as_bytes()).ok())
+            .filter(|s| s.len() < host.len());
+        let (labels, suffix) = match suffix {
+            Some(suffix) => (&host[..host.len() - suffix.len() - 1], suffix),
+            None => (host, "invalid")
+        };
+
+        labels
+            .split('.')
+            .map(|label| self.pseudonym("domain", label))
+            .chain(std::iter::once(suffix.to_string()))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// Replace a URL by one with a pseudonym as path. The scheme is
+    /// kept, so is the structure of the domain if asked for.
+    pub fn url(&self, url: &str) -> String {
+        let path = self.pseudonym("url", url);
+
+        match Url::parse(url) {
+            Ok(parsed) => match parsed.host() {
+                Some(Host::Domain(domain)) => {
+                    format!("{}://{}/{}", parsed.scheme(), self.host(domain), path)
+                }
+                Some(_) => format!("{}://{}/{}", parsed.scheme(), REDACTED_HOST, path),
+                None => format!("{}:{}", parsed.scheme(), path)
+            },
+            Err(_) => format!("https://{}/{}", REDACTED_HOST, path)
+        }
+    }
+
+    fn text(&self, kind: &str, text: &mut String) {
+        if !text.is_empty() {
+            *text = self.pseudonym(kind, text);
+        }
+    }
+
+    fn tab(&self, tab: &mut Tab) {
+        for url in [&mut tab.url, &mut tab.pending_url, &mut tab.fav_icon_url]
+            .into_iter()
+            .flatten()
+        {
+            *url = self.url(url);
+        }
+        if let Some(title) = tab.title.as_mut() {
+            self.text("title", title);
+        }
+        // Tells which extensions are installed.
+        if let Some(info) = tab.muted_info.as_mut() {
+            info.extension_id = None;
+        }
+    }
+
+    fn window(&self, window: &mut Window) {
+        if let Some(title) = window.nx_title.as_mut() {
+            self.text("title", title);
+        }
+        for tab in window.tabs.iter_mut().flatten() {
+            self.tab(tab);
+        }
+    }
+
+    fn session(&self, session: &mut SavedSession) {
+        self.text("name", &mut session.name);
+
+        let tags: Vec<String> = session
+            .tag_list()
+            .iter()
+            .map(|t| self.pseudonym("tag", &t.to_lowercase()))
+            .collect();
+        session.tags = format_tags(&tags);
+
+        for window in session.windows.iter_mut() {
+            self.window(window);
+        }
+    }
+
+    /// Redact a backup. Structure, counts, timestamps and the flags
+    /// of windows and tabs are kept. What identifies the browser and
+    /// installation is removed.
+    pub fn backup(&self, backup: &mut Backup) {
+        backup.sb_installation_id.clear();
+        backup.ua.clear();
+        backup.language.clear();
+
+        for session in backup.sessions.iter_mut() {
+            self.session(session);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::types::Json;
+
+    use super::Redactor;
+    use crate::chrome::tab::{MutedInfo, Tab};
+    use crate::chrome::window::Window;
+    use crate::session_buddy::backup::{validate_str, Backup};
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::session_buddy::database::{collect_saved_sessions, connect, import, saved_sessions};
+    use crate::session_buddy::session::SavedSession;
+
+    #[test]
+    fn redacts_urls_consistently() {
+        let redactor = Redactor::new(Some("key"), false).unwrap();
+        let url = redactor.url("https://www.example.co.uk/private?q=1");
+        assert!(url.starts_with("https://redacted.invalid/"));
+        assert!(!url.contains("example"));
+        assert_eq!(url, redactor.url("https://www.example.co.uk/private?q=1"));
+        assert_ne!(url, redactor.url("https://www.example.co.uk/"));
+        assert_ne!(
+            redactor.pseudonym("url", "a"),
+            redactor.pseudonym("title", "a")
+        );
+        assert_eq!(
+            redactor.url("about:blank"),
+            format!("about:{}", redactor.pseudonym("url", "about:blank"))
+        );
+
+        let other = Redactor::new(Some("other"), false).unwrap();
+        assert_ne!(url, other.url("https://www.example.co.uk/private?q=1"));
+
+        let redactor = Redactor::new(Some("key"), true).unwrap();
+        let a = redactor.url("https://www.example.co.uk/a");
+        let b = redactor.url("http://mail.example.co.uk/b");
+        let host = |url: &str| url.split('/').nth(2).unwrap().to_string();
+        assert!(host(&a).ends_with(".co.uk"));
+        assert_eq!(host(&a).split('.').count(), 4);
+        assert_eq!(
+            host(&a).split_once('.').unwrap().1,
+            host(&b).split_once('.').unwrap().1
+        );
+        assert!(b.starts_with("http://"));
+    }
+
+    #[tokio::test]
+    async fn redacted_backups_are_valid() {
+        let mut backup = Backup {
+            ua: "Mozilla/5.0".to_string(),
+            sb_installation_id: "install-id-42".to_string(),
+            sessions: vec![SavedSession {
+                type_: "saved".to_string(),
+                name: "Holidays".to_string(),
+                tags: "private".to_string(),
+                windows: Json(vec![Window {
+                    nx_title: Some("Flights".to_string()),
+                    tabs: Some(vec![Tab {
+                        pinned: true,
+                        url: Some("https://example.com/booking?id=1".to_string()),
+                        title: Some("Booking".to_string()),
+                        muted_info: Some(MutedInfo {
+                            extension_id: Some("abcdefghijklmnop".to_string()),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }]),
+                    ..Default::default()
+                }]),
+                unfiltered_tab_count: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        Redactor::new(None, true).unwrap().backup(&mut backup);
+
+        let json = serde_json::to_string(&backup).unwrap();
+        assert_eq!(validate_str(&json).findings, vec![]);
+        for secret in [
+            "Mozilla",
+            "install-id-42",
+            "Holidays",
+            "private",
+            "Flights",
+            "example",
+            "Booking",
+            "abcdefghijklmnop"
+        ] {
+            assert!(!json.contains(secret), "{}", secret);
+        }
+        let tab = &backup.sessions[0].windows[0].tabs.as_ref().unwrap()[0];
+        assert!(tab.pinned);
+        assert_eq!(backup.sessions[0].unfiltered_tab_count, 1);
+
+        let (dir, db) = scratch_db().await;
+        let file = dir.path().join("redacted.json");
+        std::fs::write(&file, &json).unwrap();
+        let sessions = collect_saved_sessions(&[file]).await.unwrap();
+        import(&mut connect(&db).await.unwrap(), &sessions)
+            .await
+            .unwrap();
+        let imported = saved_sessions(&db).await.unwrap();
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].name, backup.sessions[0].name);
+        assert_eq!(imported[0].tags, backup.sessions[0].tags);
+        assert_eq!(imported[0].windows, backup.sessions[0].windows);
+    }
+}
@@ -0,0 +1,271 @@
+use std::path::Path;
+
+use sqlx::types::{Json, JsonValue};
+use sqlx::{Connection, Row};
+
+use crate::chrome::window::Window;
+use crate::session_buddy::database::connect;
+use crate::session_buddy::report::Report;
+use crate::session_buddy::session::SavedSession;
+use crate::Error;
+
+/// Tables holding sessions with a windows column and derived counts.
+const TABLES: [&str; 2] = ["SavedSessions", "PreviousSessions"];
+
+/// Repair sessions written by other tools: normalize the `windows`
+/// column, fill in missing `tabs` arrays and tab indices, recompute
+/// all window and tab counts and fix `deleted` flags. Every change
+/// is recorded in the returned report. Rows which cannot be repaired
+/// are reported as errors and left untouched. Nothing is written if
+/// dry_run is set.
+pub async fn repair(path: &Path, dry_run: bool) -> Result<Report, Error> {
+    let mut conn = connect(path).await?;
+    let mut tx = conn.begin().await?;
+    let mut report = Report::new();
+
+    for table in TABLES {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT id, deleted, windows, unfilteredWindowCount,
+                filteredWindowCount, unfilteredTabCount, filteredTabCount
+            FROM {}
+            "#,
+            table
+        ))
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for row in rows.iter() {
+            let id: i64 = row.try_get("id")?;
+            let location = format!("{}[{}]", table, id);
+
+            let raw: Option<String> = row.try_get("windows")?;
+            let Some((windows, parsed)) = repair_windows(&mut report, &location, raw.as_deref())
+            else {
+                continue;
+            };
+
+            let session = SavedSession {
+                windows: Json(parsed),
+                ..Default::default()
+            };
+
+            let mut changed = raw
+                .as_deref()
+                .and_then(|r| serde_json::from_str::<JsonValue>(r).ok())
+                .as_ref()
+                != Some(&windows);
+
+            let deleted: Option<String> = row.try_get("deleted")?;
+            let repaired = repair_deleted(deleted.as_deref());
+            if deleted.as_deref() != Some(repaired) {
+                report.info(
+                    "deleted",
+                    &location,
+                    format!("deleted {:?} -> {:?}", deleted, repaired)
+                );
+                changed = true;
+            }
+
+            let counts = [
+                ("unfilteredWindowCount", session.count_windows()),
+                ("filteredWindowCount", session.count_windows()),
+                ("unfilteredTabCount", session.count_tabs()),
+                ("filteredTabCount", session.count_tabs())
+            ];
+
+            for (column, count) in counts {
+                let stored: Option<i64> = row.try_get(column)?;
+                if stored != Some(count.into()) {
+                    report.info(
+                        "count",
+                        &location,
+                        format!("{} {:?} -> {}", column, stored, count)
+                    );
+                    changed = true;
+                }
+            }
+
+            if !changed || dry_run {
+                continue;
+            }
+
+            sqlx::query(&format!(
+                r#"
+                UPDATE {} SET
+                    deleted = $1,
+                    windows = $2,
+                    unfilteredWindowCount = $3,
+                    filteredWindowCount = $3,
+                    unfilteredTabCount = $4,
+                    filteredTabCount = $4
+                WHERE id = $5
+                "#,
+                table
+            ))
+            .bind(repaired)
+            .bind(windows.to_string())
+            .bind(session.count_windows())
+            .bind(session.count_tabs())
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+/// Anything that doesn't look like a truthy value is considered not
+/// deleted.
+fn repair_deleted(deleted: Option<&str>) -> &'static str {
+    match deleted.map(|d| d.trim().to_lowercase()).as_deref() {
+        Some("true") | Some("1") | Some("yes") => "true",
+        _ => "false"
+    }
+}
+
+/// Bring the contents of a `windows` column into the shape the
+/// extension expects. Returns the repaired JSON and the windows it
+/// describes, or None if that is not possible.
+fn repair_windows(
+    report: &mut Report,
+    location: &str,
+    raw: Option<&str>
+) -> Option<(JsonValue, Vec<Window>)> {
+    let mut windows = match raw {
+        Some(raw) => match serde_json::from_str::<JsonValue>(raw) {
+            Ok(w) => w,
+            Err(e) => {
+                report.error("windows", location, format!("windows is not JSON: {}", e));
+                return None;
+            }
+        },
+        None => {
+            report.info("windows", location, "windows NULL -> []");
+            JsonValue::Array(vec![])
+        }
+    };
+
+    // Some tools store the JSON as a JSON string.
+    if let Some(inner) = windows.as_str() {
+        match serde_json::from_str::<JsonValue>(inner) {
+            Ok(w) => {
+                report.info("windows", location, "decoded double encoded windows");
+                windows = w;
+            }
+            Err(e) => {
+                report.error("windows", location, format!("windows is not JSON: {}", e));
+                return None;
+            }
+        }
+    }
+
+    if windows.is_object() {
+        report.info("windows", location, "wrapped single window into a list");
+        windows = JsonValue::Array(vec![windows]);
+    }
+
+    let Some(list) = windows.as_array_mut() else {
+        report.error("windows", location, "windows is not a list");
+        return None;
+    };
+
+    for (i, window) in list.iter_mut().enumerate() {
+        let location = format!("{}.windows[{}]", location, i);
+
+        let Some(window) = window.as_object_mut() else {
+            report.error("windows", location, "window is not an object");
+            return None;
+        };
+
+        let tabs = window.entry("tabs").or_insert(JsonValue::Null);
+
+        if tabs.is_null() {
+            report.info("tabs", &location, "filled in missing tabs array");
+            *tabs = JsonValue::Array(vec![]);
+        }
+
+        let Some(tabs) = tabs.as_array_mut() else {
+            report.error("tabs", location, "tabs is not a list");
+            return None;
+        };
+
+        for (j, tab) in tabs.iter_mut().enumerate() {
+            let Some(tab) = tab.as_object_mut() else {
+                report.error(
+                    "tabs",
+                    format!("{}.tabs[{}]", location, j),
+                    "tab is not an object"
+                );
+                return None;
+            };
+
+            let index = tab.get("index").and_then(|i| i.as_u64());
+            if index != Some(j as u64) {
+                report.info(
+                    "index",
+                    format!("{}.tabs[{}]", location, j),
+                    format!("index {:?} -> {}", index, j)
+                );
+                tab.insert("index".to_string(), j.into());
+            }
+        }
+    }
+
+    match serde_json::from_value::<Vec<Window>>(windows.clone()) {
+        Ok(parsed) => Some((windows, parsed)),
+        Err(e) => {
+            report.error(
+                "windows",
+                location,
+                format!("windows is not a list of windows: {}", e)
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{repair_deleted, repair_windows};
+    use crate::session_buddy::report::Report;
+
+    #[test]
+    fn deleted_flags() {
+        assert_eq!(repair_deleted(None), "false");
+        assert_eq!(repair_deleted(Some("TRUE")), "true");
+        assert_eq!(repair_deleted(Some("0")), "false");
+    }
+
+    #[test]
+    fn fills_in_tabs_and_indices() {
+        let mut report = Report::new();
+        let raw = json!([{ "focused": true }, { "tabs": [{ "url": "a" }, { "index": 1 }] }]);
+        let windows =
+            repair_windows(&mut report, "SavedSessions[1]", Some(&raw.to_string())).map(|(w, _)| w);
+
+        assert_eq!(
+            windows,
+            Some(json!([
+                { "focused": true, "tabs": [] },
+                { "tabs": [{ "url": "a", "index": 0 }, { "index": 1 }] }
+            ]))
+        );
+        assert_eq!(report.findings.len(), 2);
+    }
+
+    #[test]
+    fn decodes_double_encoded_windows() {
+        let mut report = Report::new();
+        let raw = json!(json!({ "tabs": [] }).to_string());
+        let windows =
+            repair_windows(&mut report, "SavedSessions[1]", Some(&raw.to_string())).map(|(w, _)| w);
+
+        assert_eq!(windows, Some(json!([{ "tabs": [] }])));
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+/// How bad a finding is. Ordered from harmless to fatal, so the
+/// worst finding of a report is simply the maximum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error"
+        })
+    }
+}
+
+/// A single problem found while validating a database or backup.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+
+    /// Kind of the problem, e.g. `missing_field` or `timestamp`.
+    /// Used to summarize a report.
+    pub category: &'static str,
+
+    /// Where the problem was found, e.g.
+    /// `SavedSessions[12].windows[3].tabs[17]`.
+    pub location: String,
+
+    pub message: String
+}
+
+/// Collection of findings produced by a validation run.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Report {
+    pub findings: Vec<Finding>
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Report {
+            ..Default::default()
+        }
+    }
+
+    pub fn push(
+        &mut self,
+        severity: Severity,
+        category: &'static str,
+        location: impl Into<String>,
+        message: impl Into<String>
+    ) {
+        self.findings.push(Finding {
+            severity,
+            category,
+            location: location.into(),
+            message: message.into()
+        });
+    }
+
+    pub fn info(
+        &mut self,
+        category: &'static str,
+        location: impl Into<String>,
+        message: impl Into<String>
+    ) {
+        self.push(Severity::Info, category, location, message);
+    }
+
+    pub fn warning(
+        &mut self,
+        category: &'static str,
+        location: impl Into<String>,
+        message: impl Into<String>
+    ) {
+        self.push(Severity::Warning, category, location, message);
+    }
+
+    pub fn error(
+        &mut self,
+        category: &'static str,
+        location: impl Into<String>,
+        message: impl Into<String>
+    ) {
+        self.push(Severity::Error, category, location, message);
+    }
+
+    /// Returns the severity of the worst finding, or None if there
+    /// are no findings at all.
+    pub fn worst(&self) -> Option<Severity> {
+        self.findings.iter().map(|f| f.severity).max()
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .count()
+    }
+
+    /// Number of findings per category.
+    pub fn summary(&self) -> BTreeMap<&'static str, usize> {
+        let mut summary = BTreeMap::new();
+        for finding in self.findings.iter() {
+            *summary.entry(finding.category).or_insert(0) += 1;
+        }
+        summary
+    }
+
+    /// Exit code reflecting the worst finding: 0 if there is
+    /// nothing worse than info, 1 for warnings and 2 for errors.
+    pub fn exit_code(&self) -> i32 {
+        match self.worst() {
+            None | Some(Severity::Info) => 0,
+            Some(Severity::Warning) => 1,
+            Some(Severity::Error) => 2
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in self.findings.iter() {
+            writeln!(
+                f,
+                "{:<7} {}: {}",
+                finding.severity, finding.location, finding.message
+            )?;
+        }
+        for (category, count) in self.summary() {
+            writeln!(f, "{:>7} {}", count, category)?;
+        }
+        write!(
+            f,
+            "{} errors, {} warnings, {} infos",
+            self.count(Severity::Error),
+            self.count(Severity::Warning),
+            self.count(Severity::Info)
+        )
+    }
+}
+
+impl Serialize for Report {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Report", 2)?;
+        s.serialize_field("findings", &self.findings)?;
+        s.serialize_field("summary", &self.summary())?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Report, Severity};
+
+    #[test]
+    fn worst_and_exit_code() {
+        let mut report = Report::new();
+        assert_eq!(report.worst(), None);
+        assert_eq!(report.exit_code(), 0);
+
+        report.info("setting", "Settings", "just saying");
+        assert_eq!(report.exit_code(), 0);
+
+        report.error("windows", "SavedSessions[1]", "broken");
+        report.warning("count", "SavedSessions[2]", "odd");
+        report.warning("count", "SavedSessions[3]", "odd");
+        assert_eq!(report.worst(), Some(Severity::Error));
+        assert_eq!(report.exit_code(), 2);
+        assert_eq!(report.summary().get("count"), Some(&2));
+    }
+}
@@ -0,0 +1,253 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use regex::Regex;
+use serde::Serialize;
+use sqlx::Connection;
+
+use crate::chrome::tab::Tab;
+use crate::session_buddy::database::{connect, update_saved_session_windows};
+use crate::session_buddy::filter::Filter;
+use crate::session_buddy::normalize::Rules;
+use crate::session_buddy::session::SavedSession;
+use crate::Error;
+
+/// A changed field of a tab.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    pub session_id: i64,
+    pub session_name: String,
+    /// Position of the window in the session.
+    pub window: usize,
+    /// Position of the tab in the window.
+    pub tab: usize,
+    /// `url`, `pending_url` or `title`.
+    pub field: &'static str,
+    pub old: String,
+    pub new: String
+}
+
+impl Change {
+    /// Number of distinct sessions changes were made to.
+    pub fn sessions(changes: &[Change]) -> usize {
+        let mut ids: Vec<i64> = changes.iter().map(|c| c.session_id).collect();
+        ids.dedup();
+        ids.len()
+    }
+}
+
+/// Change the tabs of all saved sessions matching the filter with f,
+/// in one transaction. Changed sessions get their counts recomputed
+/// and their modification time set to now. Nothing is written on a
+/// dry run, nor if the changes differ from the expected ones, e.g.
+/// of a preview, because the database changed meanwhile. Returns
+/// what changed, or would have.
+pub async fn rewrite_tabs<F>(
+    db: &Path,
+    filter: &Filter,
+    dry_run: bool,
+    expected: Option<&[Change]>,
+    mut f: F
+) -> Result<Vec<Change>, Error>
+where
+    F: FnMut(&mut Tab)
+{
+    let mut conn = connect(db).await?;
+    let mut tx = conn.begin().await?;
+    let mut changes = vec![];
+
+    let sessions = sqlx::query_as::<_, SavedSession>("SELECT * FROM SavedSessions")
+        .fetch_all(&mut *tx)
+        .await?;
+
+    for mut session in sessions.into_iter().filter(|s| filter.matches(s)) {
+        let before = changes.len();
+        let id = session.id.map(i64::from).unwrap_or_default();
+
+        for (w, window) in session.windows.iter_mut().enumerate() {
+            for (t, tab) in window.tabs.iter_mut().flatten().enumerate() {
+                let url = tab.url.clone();
+                let pending_url = tab.pending_url.clone();
+                let title = tab.title.clone();
+                f(tab);
+
+                for (field, old, new) in [
+                    ("url", url, &tab.url),
+                    ("pending_url", pending_url, &tab.pending_url),
+                    ("title", title, &tab.title)
+                ] {
+                    if &old != new {
+                        changes.push(Change {
+                            session_id: id,
+                            session_name: session.name.clone(),
+                            window: w,
+                            tab: t,
+                            field,
+                            old: old.unwrap_or_default(),
+                            new: new.clone().unwrap_or_default()
+                        });
+                    }
+                }
+            }
+        }
+
+        if changes.len() > before && !dry_run {
+            update_saved_session_windows(&mut tx, &session).await?;
+        }
+    }
+
+    if expected.is_some_and(|expected| expected != changes) {
+        return Err(Error::Conflict(
+            "the database changed since the preview, nothing was rewritten".to_string()
+        ));
+    }
+
+    if !dry_run {
+        tx.commit().await?;
+    }
+
+    Ok(changes)
+}
+
+/// Normalize the URLs of all tabs, e.g. to remove tracking
+/// parameters for good.
+pub async fn rewrite_urls(
+    db: &Path,
+    filter: &Filter,
+    rules: &Rules,
+    dry_run: bool
+) -> Result<Vec<Change>, Error> {
+    rewrite_tabs(db, filter, dry_run, None, |tab| {
+        if let Some(url) = tab.url.as_mut() {
+            *url = rules.normalize(url);
+        }
+    })
+    .await
+}
+
+/// Replace every match of a regex, see [`Regex::replace_all`] for
+/// the syntax of the replacement, e.g. `$1` or `${host}`.
+#[derive(Debug, Clone)]
+pub struct Replace {
+    pub from: Regex,
+    pub to: String
+}
+
+impl Replace {
+    fn apply(&self, s: &mut Option<String>) {
+        if let Some(s) = s.as_mut() {
+            if let Cow::Owned(replaced) = self.from.replace_all(s, self.to.as_str()) {
+                *s = replaced;
+            }
+        }
+    }
+}
+
+/// Rewrite the URLs and pending URLs of all tabs and, if given,
+/// their titles. With a preview, only exactly the previewed changes
+/// are applied, see [`rewrite_tabs`].
+pub async fn rewrite(
+    db: &Path,
+    filter: &Filter,
+    url: &Replace,
+    title: Option<&Replace>,
+    dry_run: bool,
+    preview: Option<&[Change]>
+) -> Result<Vec<Change>, Error> {
+    rewrite_tabs(db, filter, dry_run, preview, |tab| {
+        url.apply(&mut tab.url);
+        url.apply(&mut tab.pending_url);
+        if let Some(title) = title {
+            title.apply(&mut tab.title);
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+    use sqlx::types::Json;
+
+    use super::{rewrite, Replace};
+    use crate::chrome::tab::Tab;
+    use crate::chrome::window::Window;
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::session_buddy::database::{connect, insert_saved_session, saved_sessions};
+    use crate::session_buddy::filter::Filter;
+    use crate::session_buddy::session::SavedSession;
+    use crate::ErrorKind;
+
+    #[tokio::test]
+    async fn rewrites_urls_and_titles() {
+        let (_dir, db) = scratch_db().await;
+        let tab = |url: &str, title: &str| Tab {
+            url: Some(url.to_string()),
+            pending_url: Some(url.to_string()),
+            title: Some(title.to_string()),
+            ..Default::default()
+        };
+        let session = SavedSession {
+            deleted: "false".to_string(),
+            windows: Json(vec![Window {
+                tabs: Some(vec![
+                    tab("https://www.reddit.com/r/rust", "r/rust - Reddit"),
+                    tab("https://example.com/", "Example"),
+                ]),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        insert_saved_session(&mut connect(&db).await.unwrap(), &session)
+            .await
+            .unwrap();
+
+        let url = Replace {
+            from: Regex::new(r"^https://(www\.)?reddit\.com/").unwrap(),
+            to: "https://old.reddit.com/".to_string()
+        };
+        let title = Replace {
+            from: Regex::new(" - Reddit$").unwrap(),
+            to: " - old Reddit".to_string()
+        };
+
+        let preview = rewrite(&db, &Filter::new(), &url, Some(&title), true, None)
+            .await
+            .unwrap();
+        let fields: Vec<&str> = preview.iter().map(|c| c.field).collect();
+        assert_eq!(fields, ["url", "pending_url", "title"]);
+        assert_eq!(preview[0].new, "https://old.reddit.com/r/rust");
+        let before = saved_sessions(&db).await.unwrap().remove(0);
+        assert_eq!(before.windows, session.windows);
+
+        // Changes differing from the preview abort without writing.
+        assert_eq!(
+            rewrite(&db, &Filter::new(), &url, None, false, Some(&preview))
+                .await
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Conflict
+        );
+        assert_eq!(saved_sessions(&db).await.unwrap().remove(0), before);
+
+        let changes = rewrite(
+            &db,
+            &Filter::new(),
+            &url,
+            Some(&title),
+            false,
+            Some(&preview)
+        )
+        .await
+        .unwrap();
+        assert_eq!(changes, preview);
+        let after = saved_sessions(&db).await.unwrap().remove(0);
+        let tab = &after.windows[0].tabs.as_ref().unwrap()[0];
+        assert_eq!(
+            tab.pending_url.as_deref(),
+            Some("https://old.reddit.com/r/rust")
+        );
+        assert_eq!(tab.title.as_deref(), Some("r/rust - old Reddit"));
+        assert!(after.modification_date_time > before.modification_date_time);
+    }
+}
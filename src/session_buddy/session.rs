@@ -8,6 +8,7 @@ use sqlx::types::chrono::Utc;
 use sqlx::types::Json;
 use sqlx::types::JsonValue;
 
+use crate::chrome::tab::Tab;
 use crate::chrome::window::Window;
 
 #[derive(Serialize, Default)]
@@ -27,6 +28,53 @@ fn default_deleted() -> String {
     "false".to_string()
 }
 
+/// Parse the tags column. Tags are stored as a JSON list, but older
+/// or foreign databases may have them comma separated.
+pub fn parse_tags(tags: &str) -> Vec<String> {
+    serde_json::from_str::<Vec<String>>(tags).unwrap_or_else(|_| {
+        tags.split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect()
+    })
+}
+
+/// Format tags for the tags column. No tags is an empty string.
+pub fn format_tags(tags: &[String]) -> String {
+    if tags.is_empty() {
+        String::new()
+    } else {
+        serde_json::to_string(tags).unwrap_or_default()
+    }
+}
+
+/// Tags are written to backups as a list, under a key the extension
+/// doesn't know and ignores.
+mod tags {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::{format_tags, parse_tags};
+
+    pub fn serialize<S: Serializer>(tags: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(parse_tags(tags))
+    }
+
+    /// Accepts a list or the string found in the tags column.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Tags {
+            List(Vec<String>),
+            Column(String)
+        }
+
+        Ok(match Tags::deserialize(deserializer)? {
+            Tags::List(tags) => format_tags(&tags),
+            Tags::Column(tags) => format_tags(&parse_tags(&tags))
+        })
+    }
+}
+
 //impl<'de> Deserialize<'de> for Session {
 //    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
 //    where
@@ -101,7 +149,14 @@ pub struct SavedSession {
     #[serde(default, skip_serializing_if = "is_default", rename = "modified")]
     pub modification_date_time: DateTime<Utc>,
 
-    #[serde(default, skip_serializing_if = "is_default")]
+    // Key "tags" is not present in backups of the extension.
+    #[serde(
+        default,
+        rename = "sbh_tags",
+        alias = "tags",
+        skip_serializing_if = "is_default",
+        with = "tags"
+    )]
     pub tags: String,
 
     // users (IGNORED)
@@ -139,45 +194,188 @@ pub struct SavedSession {
 impl SavedSession {
     // TODO Implement functions for filtered window/tab count
 
+    /// Whether the session is flagged as deleted.
+    pub fn is_deleted(&self) -> bool {
+        self.deleted == "true"
+    }
+
+    /// Number of windows. Saturates at i32::MAX, which is what the
+    /// database column can hold.
     pub fn count_windows(&self) -> i32 {
-        self.windows.len().try_into().expect("USIZE->I32 FAILURE")
+        self.windows.len().try_into().unwrap_or(i32::MAX)
     }
 
+    /// Number of tabs in all windows. Windows without a tabs array
+    /// count as empty. Saturates at i32::MAX.
     pub fn count_tabs(&self) -> i32 {
+        count_tabs(&self.windows)
+    }
+
+    /// Tags of the session.
+    pub fn tag_list(&self) -> Vec<String> {
+        parse_tags(&self.tags)
+    }
+
+    /// Add a tag unless the session already has it, ignoring case.
+    /// Returns whether the tags changed.
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        if self.has_tag(tag) {
+            return false;
+        }
+        let mut tags = self.tag_list();
+        tags.push(tag.to_string());
+        self.tags = format_tags(&tags);
+        true
+    }
+
+    /// Remove a tag, ignoring case. Returns whether the tags
+    /// changed.
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let mut tags = self.tag_list();
+        let len = tags.len();
+        tags.retain(|t| !t.eq_ignore_ascii_case(tag));
+        if tags.len() == len {
+            return false;
+        }
+        self.tags = format_tags(&tags);
+        true
+    }
+
+    /// Whether the session has a tag, ignoring case.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tag_list().iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// Set the index of every tab to its position in its window and
+    /// drop windows without tabs.
+    pub fn reindex_tabs(&mut self) {
+        for window in self.windows.iter_mut() {
+            for (i, tab) in window.tabs.iter_mut().flatten().enumerate() {
+                tab.index = i as i64;
+            }
+        }
         self.windows
-            .iter()
-            .map(|w| {
-                w.tabs
-                    .as_ref()
-                    .unwrap()
-                    .len()
-                    .try_into()
-                    .expect("USIZE->I32 FAILURE")
-            })
-            .collect::<Vec<i32>>()
-            .iter()
-            .sum::<i32>()
+            .retain(|w| w.tabs.as_ref().is_some_and(|t| !t.is_empty()));
+    }
+
+    /// Keep only the tabs f returns true for. Tabs left in a window
+    /// are re-indexed and windows left without tabs are dropped.
+    /// Returns the number of removed tabs.
+    pub fn retain_tabs<F>(&mut self, f: F) -> usize
+    where
+        F: FnMut(&Tab) -> bool
+    {
+        retain_tabs(&mut self.windows, f)
     }
 }
 
-impl From<&JsonValue> for SavedSession {
-    fn from(s: &JsonValue) -> Self {
-        serde_json::from_str(&s.to_string()).unwrap()
+fn count_tabs(windows: &[Window]) -> i32 {
+    windows
+        .iter()
+        .map(|w| w.tabs.as_ref().map_or(0, |t| t.len()))
+        .sum::<usize>()
+        .try_into()
+        .unwrap_or(i32::MAX)
+}
+
+fn retain_tabs<F>(windows: &mut Vec<Window>, mut f: F) -> usize
+where
+    F: FnMut(&Tab) -> bool
+{
+    let mut removed = 0;
+
+    for window in windows.iter_mut() {
+        if let Some(tabs) = window.tabs.as_mut() {
+            let before = tabs.len();
+            tabs.retain(|t| f(t));
+            if tabs.len() != before {
+                removed += before - tabs.len();
+                for (i, tab) in tabs.iter_mut().enumerate() {
+                    tab.index = i as i64;
+                }
+            }
+        }
+    }
+
+    if removed > 0 {
+        windows.retain(|w| w.tabs.as_ref().is_some_and(|t| !t.is_empty()));
     }
+
+    removed
 }
 
+impl TryFrom<&JsonValue> for SavedSession {
+    type Error = serde_json::Error;
+
+    fn try_from(s: &JsonValue) -> Result<Self, Self::Error> {
+        SavedSession::deserialize(s)
+    }
+}
+
+/// Snapshot of an earlier browsing session, recorded automatically
+/// by the extension.
 #[derive(sqlx::FromRow, Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[sqlx(rename_all = "camelCase")]
 pub struct PreviousSession {
-    id: i32,
-    recording_date_time: DateTime<Utc>,
-    creation_date_time: DateTime<Utc>,
-    users: String,
-    deleted: String,
-    thumbnail: String,
-    windows: Json<Vec<Window>>,
-    unfiltered_window_count: i32,
-    filtered_window_count: i32,
-    unfiltered_tab_count: i32,
-    filtered_tab_count: i32
+    pub id: i32,
+    pub recording_date_time: DateTime<Utc>,
+    pub creation_date_time: DateTime<Utc>,
+    pub users: Option<String>,
+    pub deleted: String,
+    pub thumbnail: Option<String>,
+    pub windows: Json<Vec<Window>>,
+    pub unfiltered_window_count: i32,
+    pub filtered_window_count: i32,
+    pub unfiltered_tab_count: i32,
+    pub filtered_tab_count: i32
+}
+
+impl PreviousSession {
+    /// Whether the session is flagged as deleted.
+    pub fn is_deleted(&self) -> bool {
+        self.deleted == "true"
+    }
+
+    /// Number of windows, see [`SavedSession::count_windows`].
+    pub fn count_windows(&self) -> i32 {
+        self.windows.len().try_into().unwrap_or(i32::MAX)
+    }
+
+    /// Number of tabs, see [`SavedSession::count_tabs`].
+    pub fn count_tabs(&self) -> i32 {
+        count_tabs(&self.windows)
+    }
+
+    /// Keep only the tabs f returns true for, see
+    /// [`SavedSession::retain_tabs`].
+    pub fn retain_tabs<F>(&mut self, f: F) -> usize
+    where
+        F: FnMut(&Tab) -> bool
+    {
+        retain_tabs(&mut self.windows, f)
+    }
+
+    /// Turn the snapshot into a SavedSession named after the time it
+    /// was recorded. That is also its generation time, its creation
+    /// and modification time is now.
+    pub fn to_saved_session(&self) -> SavedSession {
+        let now = Utc::now();
+        SavedSession {
+            type_: "saved".to_string(),
+            name: format!(
+                "Previous session from {}",
+                self.recording_date_time.format("%Y-%m-%d %H:%M")
+            ),
+            generation_date_time: self.recording_date_time,
+            creation_date_time: now,
+            modification_date_time: now,
+            deleted: "false".to_string(),
+            windows: self.windows.clone(),
+            unfiltered_window_count: self.unfiltered_window_count,
+            filtered_window_count: self.filtered_window_count,
+            unfiltered_tab_count: self.unfiltered_tab_count,
+            filtered_tab_count: self.filtered_tab_count,
+            ..Default::default()
+        }
+    }
 }
@@ -1,4 +1,3 @@
-use sqlx::Connection;
 use std::path::Path;
 
 use serde::Deserialize;
@@ -7,6 +6,9 @@ use sqlx::types::chrono::DateTime;
 use sqlx::types::chrono::Utc;
 use sqlx::SqliteConnection;
 
+use crate::session_buddy::database::connect;
+use crate::Error;
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 /// UserSettings may have no fields at all.
@@ -40,12 +42,9 @@ pub struct UserSettings {
 // skip_serializing_if = "Map::is_empty"
 // skip_serializing_if = "Vec::is_empty"
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize)]
-#[sqlx(rename_all = "camelCase")]
-struct Setting {
-    key: String,
-    value: String
-}
+/// Keys that have to be present in the Settings table of every
+/// database.
+pub const REQUIRED_SETTINGS: [&str; 2] = ["installationID", "installationTimeStamp"];
 
 #[derive(sqlx::FromRow, Debug, Serialize, Deserialize)]
 pub struct StringValue {
@@ -57,19 +56,38 @@ struct DateTimeValue {
     value: DateTime<Utc>
 }
 
+/// Required keys missing from the Settings table.
+pub async fn missing_required_settings(
+    conn: &mut SqliteConnection
+) -> Result<Vec<&'static str>, Error> {
+    // Values are NUMERIC, so they may be integers. Only keys matter.
+    let keys: Vec<String> = sqlx::query_scalar("SELECT key FROM Settings")
+        .fetch_all(conn)
+        .await?;
+
+    Ok(REQUIRED_SETTINGS
+        .into_iter()
+        .filter(|key| !keys.iter().any(|k| k == key))
+        .collect())
+}
+
+fn missing_setting(table: &str, name: &str) -> Error {
+    Error::Schema(format!("{} has no key {}", table, name))
+}
+
 // TODO
-pub async fn get_string_value_setting(
-    db: &Path,
-    table: &str,
-    name: &str
-) -> Result<String, sqlx::Error> {
-    let mut conn = SqliteConnection::connect(db.to_str().unwrap()).await?;
+pub async fn get_string_value_setting(db: &Path, table: &str, name: &str) -> Result<String, Error> {
+    let mut conn = connect(db).await?;
     let q = format!("SELECT value FROM {} WHERE key = ?", table);
-    Ok(sqlx::query_as::<_, StringValue>(&q)
+    match sqlx::query_as::<_, StringValue>(&q)
         .bind(name)
         .fetch_one(&mut conn)
-        .await?
-        .value)
+        .await
+    {
+        Ok(v) => Ok(v.value),
+        Err(sqlx::Error::RowNotFound) => Err(missing_setting(table, name)),
+        Err(e) => Err(e.into())
+    }
 }
 
 // TODO
@@ -77,12 +95,16 @@ pub async fn get_datetime_value_setting(
     db: &Path,
     table: &str,
     name: &str
-) -> Result<DateTime<Utc>, sqlx::Error> {
-    let mut conn = SqliteConnection::connect(db.to_str().unwrap()).await?;
+) -> Result<DateTime<Utc>, Error> {
+    let mut conn = connect(db).await?;
     let q = format!("SELECT value FROM {} WHERE key = ?", table);
-    Ok(sqlx::query_as::<_, DateTimeValue>(&q)
+    match sqlx::query_as::<_, DateTimeValue>(&q)
         .bind(name)
         .fetch_one(&mut conn)
-        .await?
-        .value)
+        .await
+    {
+        Ok(v) => Ok(v.value),
+        Err(sqlx::Error::RowNotFound) => Err(missing_setting(table, name)),
+        Err(e) => Err(e.into())
+    }
 }
@@ -0,0 +1,371 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use sqlx::Connection;
+
+use crate::chrome::window::Window;
+use crate::session_buddy::analyze::{key, public_suffix_list, GroupBy};
+use crate::session_buddy::database::{
+    connect, flag_saved_session_deleted, previous_sessions, saved_sessions,
+    update_saved_session_windows
+};
+use crate::session_buddy::filter::Filter;
+use crate::session_buddy::session::{PreviousSession, SavedSession};
+use crate::Error;
+
+/// Order of stale URLs.
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum SortBy {
+    /// Oldest first
+    #[default]
+    Age,
+    /// Alphabetically by domain, oldest first within a domain
+    Domain
+}
+
+/// A URL not seen since the cutoff.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StaleUrl {
+    pub url: String,
+    pub domain: String,
+    /// Latest time a session containing the URL was created or
+    /// recorded.
+    pub last_seen: DateTime<Utc>,
+    /// Names of the saved sessions containing the URL.
+    pub sessions: BTreeSet<String>
+}
+
+/// What pruning stale URLs did to a database.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct PruneReport {
+    pub tabs: usize,
+    /// Sessions changed, not counting deleted ones.
+    pub sessions: usize,
+    /// Sessions flagged as deleted because no tab would be left.
+    /// They keep their tabs, so restoring them from the trash undoes
+    /// the pruning.
+    pub deleted_sessions: usize
+}
+
+fn urls(windows: &[Window]) -> impl Iterator<Item = &str> {
+    windows
+        .iter()
+        .flat_map(|w| w.tabs.iter().flatten())
+        .filter_map(|t| t.url.as_deref())
+        .filter(|url| !url.is_empty())
+}
+
+/// Find the URLs of saved sessions which neither a saved session nor
+/// a previous session has contained since cutoff.
+pub fn find_stale(
+    saved: &[SavedSession],
+    previous: &[PreviousSession],
+    cutoff: DateTime<Utc>
+) -> Result<Vec<StaleUrl>, Error> {
+    let list = public_suffix_list()?;
+    let mut last_seen: HashMap<&str, DateTime<Utc>> = HashMap::new();
+    let mut sessions: HashMap<&str, BTreeSet<String>> = HashMap::new();
+
+    for session in saved.iter() {
+        for url in urls(&session.windows) {
+            let seen = last_seen.entry(url).or_insert(session.creation_date_time);
+            *seen = (*seen).max(session.creation_date_time);
+            sessions
+                .entry(url)
+                .or_default()
+                .insert(session.name.clone());
+        }
+    }
+
+    for session in previous.iter() {
+        for url in urls(&session.windows) {
+            if let Some(seen) = last_seen.get_mut(url) {
+                *seen = (*seen).max(session.recording_date_time);
+            }
+        }
+    }
+
+    Ok(last_seen
+        .into_iter()
+        .filter(|(_, seen)| *seen < cutoff)
+        .map(|(url, seen)| StaleUrl {
+            url: url.to_string(),
+            domain: key(&list, url, GroupBy::Domain, 0),
+            last_seen: seen,
+            sessions: sessions.remove(url).unwrap_or_default()
+        })
+        .collect())
+}
+
+/// Sort stale URLs, ties are broken by URL.
+pub fn sort(urls: &mut [StaleUrl], by: SortBy) {
+    urls.sort_by(|a, b| {
+        let order = match by {
+            SortBy::Age => a.last_seen.cmp(&b.last_seen),
+            SortBy::Domain => a.domain.cmp(&b.domain).then(a.last_seen.cmp(&b.last_seen))
+        };
+        order.then_with(|| a.url.cmp(&b.url))
+    });
+}
+
+/// Find the URLs of a database not seen since cutoff. Every
+/// session not flagged as deleted counts as seeing its URLs, filter
+/// only selects which of the stale URLs are reported: those of the
+/// tabs it matches.
+pub async fn stale(
+    db: &Path,
+    filter: &Filter,
+    cutoff: DateTime<Utc>,
+    by: SortBy
+) -> Result<Vec<StaleUrl>, Error> {
+    let (deleted, saved): (Vec<SavedSession>, Vec<SavedSession>) = saved_sessions(db)
+        .await?
+        .into_iter()
+        .partition(|s| s.is_deleted());
+
+    let previous: Vec<PreviousSession> = previous_sessions(db)
+        .await?
+        .into_iter()
+        .filter(|s| !s.is_deleted())
+        .collect();
+
+    let matching: HashSet<&str> = saved
+        .iter()
+        .chain(deleted.iter())
+        .filter(|s| filter.matches(s))
+        .flat_map(|s| {
+            s.windows.iter().flat_map(move |w| {
+                w.tabs
+                    .iter()
+                    .flatten()
+                    .filter(move |t| filter.matches_tab(s, w, t))
+                    .filter_map(|t| t.url.as_deref())
+            })
+        })
+        .collect();
+
+    let mut urls = find_stale(&saved, &previous, cutoff)?;
+    urls.retain(|u| matching.contains(u.url.as_str()));
+    sort(&mut urls, by);
+
+    Ok(urls)
+}
+
+/// Remove the tabs with one of urls that filter matches from the
+/// saved sessions in one transaction. Sessions left without tabs are
+/// flagged as deleted instead.
+pub async fn prune(
+    db: &Path,
+    filter: &Filter,
+    urls: &HashSet<String>
+) -> Result<PruneReport, Error> {
+    let mut report = PruneReport::default();
+    let sessions = saved_sessions(db).await?;
+
+    let mut conn = connect(db).await?;
+    let mut tx = conn.begin().await?;
+
+    for mut session in sessions.into_iter().filter(|s| filter.matches(s)) {
+        // The filter needs the window of a tab, retain_tabs only
+        // passes the tab. It visits tabs in order, so decide up front.
+        let prunable: Vec<bool> = session
+            .windows
+            .iter()
+            .flat_map(|w| {
+                w.tabs.iter().flatten().map(|t| {
+                    t.url.as_ref().is_some_and(|url| urls.contains(url))
+                        && filter.matches_tab(&session, w, t)
+                })
+            })
+            .collect();
+        let mut prunable = prunable.into_iter();
+        let removed = session.retain_tabs(|_| !prunable.next().unwrap_or_default());
+        if removed == 0 {
+            continue;
+        }
+
+        report.tabs += removed;
+
+        match (session.windows.is_empty(), session.id) {
+            (true, Some(id)) => {
+                flag_saved_session_deleted(&mut tx, id.into()).await?;
+                report.deleted_sessions += 1;
+            }
+            _ => {
+                update_saved_session_windows(&mut tx, &session).await?;
+                report.sessions += 1;
+            }
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::{TimeZone, Utc};
+    use sqlx::types::Json;
+
+    use super::{find_stale, prune, stale, SortBy};
+    use crate::chrome::tab::Tab;
+    use crate::chrome::window::Window;
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::session_buddy::database::{connect, insert_saved_session, saved_sessions};
+    use crate::session_buddy::filter::Filter;
+    use crate::session_buddy::query::Expr;
+    use crate::session_buddy::session::{PreviousSession, SavedSession};
+
+    fn window(urls: &[&str]) -> Json<Vec<Window>> {
+        Json(vec![Window {
+            tabs: Some(
+                urls.iter()
+                    .map(|url| Tab {
+                        url: Some(url.to_string()),
+                        ..Default::default()
+                    })
+                    .collect()
+            ),
+            ..Default::default()
+        }])
+    }
+
+    #[test]
+    fn finds_urls_not_seen_since_cutoff() {
+        let old = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+        let new = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let cutoff = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+
+        let saved = [
+            SavedSession {
+                name: "old".to_string(),
+                creation_date_time: old,
+                windows: window(&["https://a.example.com/", "https://b.com/", "https://c.com/"]),
+                ..Default::default()
+            },
+            SavedSession {
+                name: "new".to_string(),
+                creation_date_time: new,
+                windows: window(&["https://b.com/"]),
+                ..Default::default()
+            }
+        ];
+        let previous = [PreviousSession {
+            recording_date_time: new,
+            windows: window(&["https://c.com/"]),
+            ..Default::default()
+        }];
+
+        let stale = find_stale(&saved, &previous, cutoff).unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].url, "https://a.example.com/");
+        assert_eq!(stale[0].domain, "example.com");
+        assert_eq!(stale[0].last_seen, old);
+        assert!(stale[0].sessions.contains("old"));
+    }
+
+    #[tokio::test]
+    async fn prunes_urls_and_trashes_emptied_sessions() {
+        let (_dir, db) = scratch_db().await;
+        let mut conn = connect(&db).await.unwrap();
+        for (name, urls) in [
+            ("only stale", &["https://old.com/"][..]),
+            ("mixed", &["https://old.com/", "https://new.com/"][..]),
+            ("fresh", &["https://new.com/"][..])
+        ] {
+            let session = SavedSession {
+                name: name.to_string(),
+                deleted: "false".to_string(),
+                windows: window(urls),
+                ..Default::default()
+            };
+            insert_saved_session(&mut conn, &session).await.unwrap();
+        }
+        drop(conn);
+
+        let urls = HashSet::from(["https://old.com/".to_string()]);
+        let report = prune(&db, &Filter::new(), &urls).await.unwrap();
+        assert_eq!(
+            (report.tabs, report.sessions, report.deleted_sessions),
+            (2, 1, 1)
+        );
+
+        let sessions = saved_sessions(&db).await.unwrap();
+        let tabs = |i: usize| {
+            sessions[i]
+                .windows
+                .iter()
+                .flat_map(|w| w.tabs.iter().flatten())
+                .filter_map(|t| t.url.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(sessions[0].is_deleted());
+        assert_eq!(tabs(0), ["https://old.com/"]);
+        assert!(!sessions[1].is_deleted());
+        assert_eq!(tabs(1), ["https://new.com/"]);
+        assert_eq!(sessions[1].unfiltered_tab_count, 1);
+        assert!(!sessions[2].is_deleted());
+        assert_eq!(tabs(2), ["https://new.com/"]);
+    }
+
+    #[tokio::test]
+    async fn filter_limits_reported_and_pruned_tabs() {
+        let (_dir, db) = scratch_db().await;
+        let old = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut conn = connect(&db).await.unwrap();
+        for (name, created, urls) in [
+            (
+                "archive",
+                old,
+                &["https://old.com/", "https://docs.rs/"][..]
+            ),
+            ("recent", Utc::now(), &["https://docs.rs/"][..]),
+            ("other", old, &["https://elsewhere.com/"][..])
+        ] {
+            let session = SavedSession {
+                name: name.to_string(),
+                creation_date_time: created,
+                deleted: "false".to_string(),
+                windows: window(urls),
+                ..Default::default()
+            };
+            insert_saved_session(&mut conn, &session).await.unwrap();
+        }
+        drop(conn);
+
+        let filter = |expr: &str| Filter {
+            expr: Some(Expr::parse(expr).unwrap()),
+            ..Default::default()
+        };
+        let cutoff = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+
+        // docs.rs was seen recently in a session --where excludes.
+        let archive = filter("name=archive");
+        let found = stale(&db, &archive, cutoff, SortBy::Age).await.unwrap();
+        let urls: HashSet<String> = found.into_iter().map(|u| u.url).collect();
+        assert_eq!(urls, HashSet::from(["https://old.com/".to_string()]));
+
+        let report = prune(&db, &archive, &urls).await.unwrap();
+        assert_eq!((report.tabs, report.deleted_sessions), (1, 0));
+
+        // Only tabs matching the filter are pruned.
+        let urls = HashSet::from([
+            "https://docs.rs/".to_string(),
+            "https://elsewhere.com/".to_string()
+        ]);
+        let report = prune(&db, &filter("domain:docs.rs"), &urls).await.unwrap();
+        assert_eq!((report.tabs, report.deleted_sessions), (2, 2));
+
+        let sessions = saved_sessions(&db).await.unwrap();
+        assert!(sessions[0].is_deleted());
+        assert!(sessions[1].is_deleted());
+        assert!(!sessions[2].is_deleted());
+        assert_eq!(sessions[2].unfiltered_tab_count, 1);
+    }
+}
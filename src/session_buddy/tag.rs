@@ -0,0 +1,150 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+use sqlx::Connection;
+
+use crate::session_buddy::database::{
+    connect, saved_session, saved_sessions, update_saved_session_tags
+};
+use crate::session_buddy::filter::Filter;
+use crate::session_buddy::session::SavedSession;
+use crate::Error;
+
+/// A tag and the number of sessions having it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub sessions: usize
+}
+
+fn check(tag: &str) -> Result<&str, Error> {
+    match tag.trim() {
+        "" => Err(Error::Format("tags can't be empty".to_string())),
+        tag => Ok(tag)
+    }
+}
+
+/// All tags of the sessions matching the filter, most used first.
+/// Tags differing in case count as one, spelled as first seen.
+pub async fn list(db: &Path, filter: &Filter) -> Result<Vec<TagCount>, Error> {
+    let mut tags: BTreeMap<String, TagCount> = BTreeMap::new();
+
+    for session in saved_sessions(db)
+        .await?
+        .iter()
+        .filter(|s| filter.matches(s))
+    {
+        for tag in session.tag_list() {
+            tags.entry(tag.to_lowercase())
+                .or_insert(TagCount { tag, sessions: 0 })
+                .sessions += 1;
+        }
+    }
+
+    let mut tags: Vec<TagCount> = tags.into_values().collect();
+    tags.sort_by_key(|t| Reverse(t.sessions));
+    Ok(tags)
+}
+
+/// Change the tags of the given sessions with f, which returns
+/// whether it changed anything. Fails without changing anything if
+/// one of them doesn't exist. Returns the number of changed
+/// sessions.
+async fn update<F>(db: &Path, ids: &[i64], f: F) -> Result<usize, Error>
+where
+    F: Fn(&mut SavedSession) -> bool
+{
+    let mut conn = connect(db).await?;
+    let mut tx = conn.begin().await?;
+    let mut changed = 0;
+
+    for id in ids.iter() {
+        let mut session = saved_session(&mut tx, *id).await?;
+        if f(&mut session) {
+            update_saved_session_tags(&mut tx, &session).await?;
+            changed += 1;
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(changed)
+}
+
+/// Tag sessions. Returns the number of sessions that didn't have
+/// the tag yet.
+pub async fn add(db: &Path, ids: &[i64], tag: &str) -> Result<usize, Error> {
+    let tag = check(tag)?;
+    update(db, ids, |s| s.add_tag(tag)).await
+}
+
+/// Remove a tag from sessions. Returns the number of sessions that
+/// had it.
+pub async fn remove(db: &Path, ids: &[i64], tag: &str) -> Result<usize, Error> {
+    let tag = check(tag)?;
+    update(db, ids, |s| s.remove_tag(tag)).await
+}
+
+/// Rename a tag on all sessions. Sessions already having the new
+/// tag just lose the old one. Returns the number of changed
+/// sessions.
+pub async fn rename(db: &Path, from: &str, to: &str) -> Result<usize, Error> {
+    let from = check(from)?;
+    let to = check(to)?;
+
+    let ids: Vec<i64> = saved_sessions(db)
+        .await?
+        .iter()
+        .filter(|s| s.has_tag(from))
+        .filter_map(|s| s.id.map(i64::from))
+        .collect();
+
+    update(db, &ids, |s| {
+        s.remove_tag(from);
+        s.add_tag(to);
+        true
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::types::Json;
+
+    use super::{add, list, remove, rename};
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::session_buddy::database::{connect, insert_saved_session, saved_sessions};
+    use crate::session_buddy::filter::Filter;
+    use crate::session_buddy::session::SavedSession;
+
+    #[tokio::test]
+    async fn manages_tags() {
+        let (_dir, db) = scratch_db().await;
+        let mut conn = connect(&db).await.unwrap();
+        for tags in ["work, rust", ""] {
+            let session = SavedSession {
+                tags: tags.to_string(),
+                deleted: "false".to_string(),
+                windows: Json(vec![]),
+                ..Default::default()
+            };
+            insert_saved_session(&mut conn, &session).await.unwrap();
+        }
+
+        assert_eq!(add(&db, &[1, 2], "Rust").await.unwrap(), 1);
+        assert_eq!(remove(&db, &[1], " work ").await.unwrap(), 1);
+        assert!(remove(&db, &[1], " ").await.is_err());
+        assert_eq!(rename(&db, "rust ", "rustlang").await.unwrap(), 2);
+        assert!(add(&db, &[3], "x").await.is_err());
+
+        let tags = list(&db, &Filter::new()).await.unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!((tags[0].tag.as_str(), tags[0].sessions), ("rustlang", 2));
+        assert_eq!(
+            saved_sessions(&db).await.unwrap()[0].tags,
+            r#"["rustlang"]"#
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use log::warn;
+use sqlx::{Connection, Row};
+
+use crate::session_buddy::database::{connect, previous_sessions, saved_sessions};
+use crate::session_buddy::filter::Filter;
+use crate::Error;
+
+/// Tables with a thumbnail column.
+const TABLES: [(&str, &str); 2] = [("SavedSessions", "saved"), ("PreviousSessions", "previous")];
+
+/// What stripping thumbnails did to a database.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct StripReport {
+    /// Number of thumbnails removed.
+    pub thumbnails: u64,
+    /// Bytes of thumbnail data removed.
+    pub thumbnail_bytes: u64,
+    /// Size of the database file before stripping.
+    pub size_before: u64,
+    /// Size of the database file after stripping and VACUUM.
+    pub size_after: u64
+}
+
+/// What extracting thumbnails wrote and what it had to skip.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct ExtractReport {
+    /// Paths of the written files.
+    pub files: Vec<PathBuf>,
+    /// Thumbnails that could not be decoded, e.g.
+    /// `SavedSessions[3]: thumbnail is not a base64 data URL`.
+    pub failures: Vec<String>
+}
+
+/// Decode a thumbnail stored as data URL, e.g.
+/// `data:image/jpeg;base64,...`. Returns the file extension matching
+/// its MIME type and the image data.
+pub fn decode(data_url: &str) -> Result<(&'static str, Vec<u8>), Error> {
+    let invalid = || Error::Format("thumbnail is not a base64 data URL".to_string());
+
+    let (header, data) = data_url
+        .strip_prefix("data:")
+        .and_then(|s| s.split_once(','))
+        .ok_or_else(invalid)?;
+
+    let mime = header.strip_suffix(";base64").ok_or_else(invalid)?;
+    let extension = match mime {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/png" => "png",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        _ => "bin"
+    };
+
+    let data = STANDARD
+        .decode(data.trim())
+        .map_err(|e| Error::Format(format!("thumbnail is not valid base64: {}", e)))?;
+
+    Ok((extension, data))
+}
+
+/// Write the thumbnails of all sessions as image files into out,
+/// named by table and session id, e.g. `saved-42.jpg`. Thumbnails
+/// that can't be decoded are logged and skipped.
+pub async fn extract(db: &Path, out: &Path, filter: &Filter) -> Result<ExtractReport, Error> {
+    // Expressions need the sessions, not just their thumbnails.
+    let matching: Option<HashSet<(&str, i64)>> = match filter.expr {
+        Some(_) => Some(
+            saved_sessions(db)
+                .await?
+                .iter()
+                .filter(|s| filter.matches(s))
+                .filter_map(|s| s.id.map(|id| ("SavedSessions", id.into())))
+                .chain(
+                    previous_sessions(db)
+                        .await?
+                        .iter()
+                        .filter(|s| filter.matches_previous(s))
+                        .map(|s| ("PreviousSessions", s.id.into()))
+                )
+                .collect()
+        ),
+        None => None
+    };
+
+    let mut conn = connect(db).await?;
+    let mut report = ExtractReport::default();
+
+    fs::create_dir_all(out)?;
+
+    for (table, prefix) in TABLES {
+        let rows = sqlx::query(&format!(
+            "SELECT id, deleted, thumbnail FROM {} WHERE thumbnail IS NOT NULL AND thumbnail != ''",
+            table
+        ))
+        .fetch_all(&mut conn)
+        .await?;
+
+        for row in rows.iter() {
+            let id: i64 = row.try_get("id")?;
+            let deleted: Option<String> = row.try_get("deleted")?;
+            if !filter.matches_deleted(deleted.as_deref() == Some("true"))
+                || matching.as_ref().is_some_and(|m| !m.contains(&(table, id)))
+            {
+                continue;
+            }
+
+            let thumbnail = row
+                .try_get("thumbnail")
+                .map_err(|_| Error::Format("thumbnail is not text".to_string()));
+            let (extension, data) = match thumbnail.and_then(decode) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    let failure = format!("{}[{}]: {}", table, id, e);
+                    warn!("Skipping thumbnail of {}", failure);
+                    report.failures.push(failure);
+                    continue;
+                }
+            };
+
+            let file = out.join(format!("{}-{}.{}", prefix, id, extension));
+            fs::write(&file, data)?;
+            report.files.push(file);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Remove all thumbnails in one transaction, then VACUUM the
+/// database, so the space is actually given back.
+pub async fn strip(db: &Path) -> Result<StripReport, Error> {
+    let mut report = StripReport {
+        size_before: fs::metadata(db)?.len(),
+        ..Default::default()
+    };
+
+    let mut conn = connect(db).await?;
+    let mut tx = conn.begin().await?;
+
+    for (table, _) in TABLES {
+        let (count, bytes): (i64, Option<i64>) = sqlx::query_as(&format!(
+            "SELECT COUNT(*), SUM(LENGTH(thumbnail)) FROM {} WHERE thumbnail IS NOT NULL",
+            table
+        ))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        report.thumbnails += count as u64;
+        report.thumbnail_bytes += bytes.unwrap_or_default() as u64;
+
+        sqlx::query(&format!(
+            "UPDATE {} SET thumbnail = NULL WHERE thumbnail IS NOT NULL",
+            table
+        ))
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    sqlx::query("VACUUM").execute(&mut conn).await?;
+    drop(conn);
+
+    report.size_after = fs::metadata(db)?.len();
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{decode, extract, strip};
+    use crate::session_buddy::database::connect;
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::session_buddy::filter::Filter;
+
+    #[test]
+    fn decodes_data_urls() {
+        let (extension, data) = decode("data:image/png;base64,iVBORw0KGgo=").unwrap();
+        assert_eq!(extension, "png");
+        assert_eq!(data, b"\x89PNG\r\n\x1a\n");
+
+        assert!(decode("https://example.com/thumbnail.png").is_err());
+        assert!(decode("data:image/png;base64,!!!").is_err());
+    }
+
+    #[tokio::test]
+    async fn extracts_and_strips_thumbnails() {
+        let (dir, db) = scratch_db().await;
+        let mut conn = connect(&db).await.unwrap();
+        let png = "data:image/png;base64,iVBORw0KGgo=";
+        for (table, thumbnail) in [
+            ("SavedSessions", png),
+            ("SavedSessions", "not a data URL"),
+            ("PreviousSessions", png)
+        ] {
+            sqlx::query(&format!(
+                "INSERT INTO {} (creationDateTime, deleted, thumbnail, windows) VALUES ($1, 'false', $2, '[]')",
+                table
+            ))
+            .bind(Utc::now())
+            .bind(thumbnail)
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        }
+        sqlx::query(
+            "INSERT INTO SavedSessions (creationDateTime, deleted, thumbnail, windows) VALUES ($1, 'false', X'89504E47', '[]')"
+        )
+        .bind(Utc::now())
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        drop(conn);
+
+        let out = dir.path().join("thumbnails");
+        let report = extract(&db, &out, &Filter::new()).await.unwrap();
+        assert_eq!(
+            report.files,
+            [out.join("saved-1.png"), out.join("previous-1.png")]
+        );
+        assert_eq!(report.failures.len(), 2);
+        assert!(report.failures[0].starts_with("SavedSessions[2]"));
+        assert_eq!(
+            report.failures[1],
+            "SavedSessions[3]: Format error: thumbnail is not text"
+        );
+        assert_eq!(
+            std::fs::read(&report.files[0]).unwrap(),
+            b"\x89PNG\r\n\x1a\n"
+        );
+
+        let report = strip(&db).await.unwrap();
+        assert_eq!(report.thumbnails, 4);
+        let left: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM SavedSessions WHERE thumbnail IS NOT NULL")
+                .fetch_one(&mut connect(&db).await.unwrap())
+                .await
+                .unwrap();
+        assert_eq!(left, 0);
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use clap::ValueEnum;
+use log::warn;
+use serde::Serialize;
+
+use crate::session_buddy::analyze::{key, public_suffix_list, GroupBy};
+use crate::session_buddy::database::{previous_sessions, saved_sessions, EARLIEST_TIMESTAMP};
+use crate::session_buddy::filter::Filter;
+use crate::session_buddy::session::SavedSession;
+use crate::Error;
+
+/// Length of a bucket.
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum Period {
+    Day,
+    /// ISO week, starting on Monday
+    Week,
+    #[default]
+    Month
+}
+
+impl Period {
+    /// First day of the period containing date.
+    pub fn start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => date,
+            Period::Week => date - Duration::days(date.weekday().num_days_from_monday().into()),
+            Period::Month => date.with_day(1).unwrap_or(date)
+        }
+    }
+
+    /// First day of the period following the one starting at start.
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => start + Duration::days(1),
+            Period::Week => start + Duration::days(7),
+            Period::Month => {
+                let (year, month) = match start.month() {
+                    12 => (start.year() + 1, 1),
+                    m => (start.year(), m + 1)
+                };
+                NaiveDate::from_ymd_opt(year, month, 1).unwrap_or(start + Duration::days(31))
+            }
+        }
+    }
+
+    /// Label of the period starting at start, e.g. `2023-04-17`,
+    /// `2023-W16` or `2023-04`.
+    pub fn label(&self, start: NaiveDate) -> String {
+        match self {
+            Period::Day => start.format("%Y-%m-%d").to_string(),
+            Period::Week => {
+                let week = start.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            Period::Month => start.format("%Y-%m").to_string()
+        }
+    }
+}
+
+/// Timestamp of a saved session to bucket it by.
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum DateField {
+    Generated,
+    #[default]
+    Created,
+    Modified
+}
+
+impl DateField {
+    fn of(&self, session: &SavedSession) -> DateTime<Utc> {
+        match self {
+            DateField::Generated => session.generation_date_time,
+            DateField::Created => session.creation_date_time,
+            DateField::Modified => session.modification_date_time
+        }
+    }
+}
+
+/// Activity within one period.
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
+pub struct Bucket {
+    pub period: String,
+    pub start: NaiveDate,
+    /// Saved sessions.
+    pub sessions: u64,
+    /// Tabs of the saved sessions.
+    pub tabs: u64,
+    /// Registrable domains of the tabs.
+    pub domains: u64,
+    /// Previous sessions recorded by the extension.
+    pub previous_sessions: u64,
+    #[serde(skip)]
+    domain_set: HashSet<String>
+}
+
+/// Bucket saved sessions by field and previous sessions by their
+/// recording time. Periods without any activity between the first
+/// and the last one are included, so the buckets can be charted
+/// as they are. Sessions with bogus timestamps, e.g. 1970, are left
+/// out, as they would stretch the timeline by decades.
+pub async fn timeline(
+    db: &Path,
+    filter: &Filter,
+    period: Period,
+    field: DateField
+) -> Result<Vec<Bucket>, Error> {
+    let list = public_suffix_list()?;
+    let mut buckets: BTreeMap<NaiveDate, Bucket> = BTreeMap::new();
+    let mut skipped = 0;
+
+    for session in saved_sessions(db)
+        .await?
+        .iter()
+        .filter(|s| filter.matches(s))
+    {
+        let date = field.of(session);
+        if date.timestamp() < EARLIEST_TIMESTAMP {
+            skipped += 1;
+            continue;
+        }
+        let start = period.start(date.date_naive());
+        let bucket = buckets.entry(start).or_default();
+
+        bucket.sessions += 1;
+        bucket.tabs += session.count_tabs() as u64;
+        bucket.domain_set.extend(
+            session
+                .windows
+                .iter()
+                .flat_map(|w| w.tabs.iter().flatten())
+                .filter_map(|t| t.url.as_deref())
+                .filter(|url| !url.is_empty())
+                .map(|url| key(&list, url, GroupBy::Domain, 0))
+        );
+    }
+
+    for session in previous_sessions(db)
+        .await?
+        .iter()
+        .filter(|s| filter.matches_previous(s))
+    {
+        if session.recording_date_time.timestamp() < EARLIEST_TIMESTAMP {
+            skipped += 1;
+            continue;
+        }
+        let start = period.start(session.recording_date_time.date_naive());
+        buckets.entry(start).or_default().previous_sessions += 1;
+    }
+
+    if skipped > 0 {
+        warn!("Skipped {} sessions with timestamps before 2009", skipped);
+    }
+
+    let (first, last) = match (buckets.keys().next(), buckets.keys().last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return Ok(vec![])
+    };
+
+    let mut timeline = vec![];
+    let mut start = first;
+    while start <= last {
+        let mut bucket = buckets.remove(&start).unwrap_or_default();
+        bucket.period = period.label(start);
+        bucket.start = start;
+        bucket.domains = bucket.domain_set.len() as u64;
+        timeline.push(bucket);
+        start = period.next(start);
+    }
+
+    Ok(timeline)
+}
+
+/// Render values as a line of block characters, scaled to the
+/// largest value.
+pub fn sparkline(values: &[u64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let max = values.iter().copied().max().unwrap_or_default();
+
+    values
+        .iter()
+        .map(|&v| match v {
+            0 => ' ',
+            v => BLOCKS[((v * 8).div_ceil(max) as usize - 1).min(7)]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use super::{sparkline, timeline, DateField, Period};
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::session_buddy::database::{connect, insert_saved_session};
+    use crate::session_buddy::filter::Filter;
+    use crate::session_buddy::session::SavedSession;
+
+    #[test]
+    fn periods() {
+        let date = NaiveDate::from_ymd_opt(2023, 12, 20).unwrap();
+
+        let week = Period::Week.start(date);
+        assert_eq!(week, NaiveDate::from_ymd_opt(2023, 12, 18).unwrap());
+        assert_eq!(Period::Week.label(week), "2023-W51");
+
+        let month = Period::Month.start(date);
+        assert_eq!(Period::Month.label(month), "2023-12");
+        assert_eq!(
+            Period::Month.next(month),
+            NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn sparklines() {
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(sparkline(&[0, 1, 7, 3]), " ▂█▄");
+    }
+
+    #[tokio::test]
+    async fn skips_bogus_timestamps() {
+        let (_dir, db) = scratch_db().await;
+        let mut conn = connect(&db).await.unwrap();
+        for created in [
+            Utc.timestamp_opt(0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2023, 1, 15, 0, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2023, 3, 15, 0, 0, 0).unwrap()
+        ] {
+            let session = SavedSession {
+                creation_date_time: created,
+                deleted: "false".to_string(),
+                ..Default::default()
+            };
+            insert_saved_session(&mut conn, &session).await.unwrap();
+        }
+        drop(conn);
+
+        let buckets = timeline(&db, &Filter::new(), Period::Month, DateField::Created)
+            .await
+            .unwrap();
+        let periods: Vec<&str> = buckets.iter().map(|b| b.period.as_str()).collect();
+        assert_eq!(periods, ["2023-01", "2023-02", "2023-03"]);
+        assert_eq!(
+            buckets.iter().map(|b| b.sessions).collect::<Vec<_>>(),
+            [1, 0, 1]
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use std::path::Path;
+
+use chrono::Utc;
+use sqlx::{Connection, QueryBuilder, Sqlite};
+
+use crate::session_buddy::database::{connect, saved_sessions};
+use crate::session_buddy::session::SavedSession;
+use crate::Error;
+
+/// All sessions flagged as deleted.
+pub async fn deleted_sessions(db: &Path) -> Result<Vec<SavedSession>, Error> {
+    Ok(saved_sessions(db)
+        .await?
+        .into_iter()
+        .filter(|s| s.is_deleted())
+        .collect())
+}
+
+/// Clear the deleted flag of the given sessions. Fails without
+/// changing anything if one of them is not flagged as deleted.
+pub async fn restore(db: &Path, ids: &[i64]) -> Result<(), Error> {
+    let mut conn = connect(db).await?;
+    let mut tx = conn.begin().await?;
+
+    for id in ids.iter() {
+        let result = sqlx::query(
+            r#"
+            UPDATE SavedSessions SET deleted = 'false', modificationDateTime = $1
+            WHERE id = $2 AND deleted = 'true'
+            "#
+        )
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound(format!(
+                "SavedSessions has no deleted session {}",
+                id
+            )));
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Permanently delete sessions flagged as deleted. If ids is empty,
+/// the whole trash is emptied. Sessions not flagged as deleted are
+/// never touched. Returns the number of purged sessions.
+pub async fn purge(db: &Path, ids: &[i64]) -> Result<u64, Error> {
+    let mut conn = connect(db).await?;
+
+    let mut query = QueryBuilder::<Sqlite>::new("DELETE FROM SavedSessions WHERE deleted = 'true'");
+
+    if !ids.is_empty() {
+        query.push(" AND id IN (");
+        let mut separated = query.separated(", ");
+        for id in ids.iter() {
+            separated.push_bind(id);
+        }
+        separated.push_unseparated(")");
+    }
+
+    Ok(query.build().execute(&mut conn).await?.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use sqlx::types::Json;
+
+    use super::{deleted_sessions, purge, restore};
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::session_buddy::database::{connect, insert_saved_session, saved_sessions};
+    use crate::session_buddy::session::SavedSession;
+
+    /// Insert sessions flagged as deleted or not, ids start at 1.
+    async fn insert(db: &Path, deleted: &[bool]) {
+        let mut conn = connect(db).await.unwrap();
+        for deleted in deleted {
+            let session = SavedSession {
+                deleted: deleted.to_string(),
+                windows: Json(vec![]),
+                ..Default::default()
+            };
+            insert_saved_session(&mut conn, &session).await.unwrap();
+        }
+    }
+
+    async fn ids(db: &Path) -> Vec<i32> {
+        saved_sessions(db)
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(|s| s.id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn restores_deleted_sessions() {
+        let (_dir, db) = scratch_db().await;
+        insert(&db, &[true, true, false]).await;
+
+        // Session 3 isn't in the trash, so nothing is restored.
+        assert!(restore(&db, &[1, 3]).await.is_err());
+        assert_eq!(deleted_sessions(&db).await.unwrap().len(), 2);
+
+        restore(&db, &[1]).await.unwrap();
+        let deleted = deleted_sessions(&db).await.unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].id, Some(2));
+    }
+
+    #[tokio::test]
+    async fn purges_only_deleted_sessions() {
+        let (_dir, db) = scratch_db().await;
+        insert(&db, &[true, true, false, true]).await;
+
+        assert_eq!(purge(&db, &[1, 3]).await.unwrap(), 1);
+        assert_eq!(ids(&db).await, [2, 3, 4]);
+
+        assert_eq!(purge(&db, &[]).await.unwrap(), 2);
+        assert_eq!(ids(&db).await, [3]);
+        assert_eq!(purge(&db, &[]).await.unwrap(), 0);
+    }
+}
@@ -1,19 +1,240 @@
+use std::path::Path;
+
 use serde::Deserialize;
 use serde::Serialize;
 use sqlx::types::chrono::DateTime;
 use sqlx::types::chrono::Utc;
+use sqlx::types::{Json, JsonValue};
+
+use crate::chrome::tab::Tab;
+use crate::chrome::window::Window;
+use crate::session_buddy::database::{connect, insert_saved_session};
+use crate::session_buddy::session::SavedSession;
+use crate::Error;
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize)]
+/// An entry of the Undo table. The extension records deletions
+/// here, with the deleted session, window or tab serialized into one
+/// of the registers.
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[sqlx(rename_all = "camelCase")]
-struct Undo {
-    id: i64,
-    creation_date_time: DateTime<Utc>,
-    tab_identifier: String,
-    action: String,
-    description: String,
-    register1: String,
-    register2: String,
-    register3: String,
-    register4: String,
-    register5: String
+pub struct Undo {
+    pub id: i64,
+    pub creation_date_time: Option<DateTime<Utc>>,
+    pub tab_identifier: Option<String>,
+    pub action: Option<String>,
+    pub description: Option<String>,
+    pub register1: Option<String>,
+    pub register2: Option<String>,
+    pub register3: Option<String>,
+    pub register4: Option<String>,
+    pub register5: Option<String>
+}
+
+impl Undo {
+    pub fn registers(&self) -> [Option<&str>; 5] {
+        [
+            self.register1.as_deref(),
+            self.register2.as_deref(),
+            self.register3.as_deref(),
+            self.register4.as_deref(),
+            self.register5.as_deref()
+        ]
+    }
+
+    /// Rebuild whatever was deleted as SavedSession. Deleted windows
+    /// and tabs are wrapped into a new session. Uses the first
+    /// register holding a session, a list of windows, a window or a
+    /// tab, and fails if that one doesn't parse.
+    pub fn to_saved_session(&self) -> Result<SavedSession, Error> {
+        let windows = self
+            .registers()
+            .into_iter()
+            .flatten()
+            .filter_map(|r| serde_json::from_str::<JsonValue>(r).ok())
+            .find_map(|r| payload(&r))
+            .transpose()
+            .map_err(|e| Error::Format(format!("Undo[{}]: {}", self.id, e)))?;
+
+        let Some((mut session, windows)) = windows else {
+            return Err(Error::Format(format!(
+                "Undo[{}]: no register holds a session, window or tab",
+                self.id
+            )));
+        };
+
+        session.id = None;
+        session.type_ = "saved".to_string();
+        session.windows = Json(windows);
+        session.deleted = "false".to_string();
+
+        if session.name.is_empty() {
+            session.name = self.description.clone().unwrap_or_default();
+        }
+        if let Some(deleted) = self.creation_date_time {
+            if session.generation_date_time == DateTime::<Utc>::default() {
+                session.generation_date_time = deleted;
+            }
+            if session.creation_date_time == DateTime::<Utc>::default() {
+                session.creation_date_time = deleted;
+            }
+        }
+        session.modification_date_time = Utc::now();
+
+        Ok(session)
+    }
+}
+
+/// Interpret a register as session, list of windows, window or tab.
+/// Returns the session's other fields, if any, and its windows, or
+/// None if the register holds none of them.
+fn payload(value: &JsonValue) -> Option<Result<(SavedSession, Vec<Window>), serde_json::Error>> {
+    if value.is_array() {
+        let windows = serde_json::from_value::<Vec<Window>>(value.clone());
+        return Some(windows.map(|w| (SavedSession::default(), w)));
+    }
+
+    let object = value.as_object()?;
+
+    if let Some(windows) = object.get("windows") {
+        // Sessions stored by the extension may have the windows
+        // serialized into a string, just like in the database.
+        let windows = match windows.as_str() {
+            Some(w) => serde_json::from_str::<Vec<Window>>(w),
+            None => serde_json::from_value::<Vec<Window>>(windows.clone())
+        };
+        let windows = match windows {
+            Ok(windows) => windows,
+            Err(e) => return Some(Err(e))
+        };
+
+        let mut session = object.clone();
+        session.insert("windows".to_string(), JsonValue::Array(vec![]));
+        session
+            .entry("type")
+            .or_insert_with(|| JsonValue::from("saved"));
+
+        let session = SavedSession::deserialize(&JsonValue::Object(session));
+        return Some(session.map(|s| (s, windows)));
+    }
+
+    if object.contains_key("tabs") {
+        let window = serde_json::from_value::<Window>(value.clone());
+        return Some(window.map(|w| (SavedSession::default(), vec![w])));
+    }
+
+    if object.contains_key("url") {
+        let tab = serde_json::from_value::<Tab>(value.clone());
+        return Some(tab.map(|t| {
+            let window = Window {
+                tabs: Some(vec![t]),
+                ..Default::default()
+            };
+            (SavedSession::default(), vec![window])
+        }));
+    }
+
+    None
+}
+
+/// All entries of the undo history, oldest first.
+pub async fn undo_entries(db: &Path) -> Result<Vec<Undo>, Error> {
+    let mut conn = connect(db).await?;
+
+    Ok(sqlx::query_as::<_, Undo>("SELECT * FROM Undo ORDER BY id")
+        .fetch_all(&mut conn)
+        .await?)
+}
+
+/// Rebuild what was deleted with the Undo entry id and insert it as
+/// a new SavedSession. The Undo entry itself is kept. Returns the id
+/// of the new session.
+pub async fn restore(db: &Path, id: i64) -> Result<i64, Error> {
+    let mut conn = connect(db).await?;
+
+    let undo = match sqlx::query_as::<_, Undo>("SELECT * FROM Undo WHERE id = ?")
+        .bind(id)
+        .fetch_one(&mut conn)
+        .await
+    {
+        Ok(undo) => undo,
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(Error::NotFound(format!("Undo has no entry {}", id)))
+        }
+        Err(e) => return Err(e.into())
+    };
+
+    let session = undo.to_saved_session()?;
+
+    Ok(insert_saved_session(&mut conn, &session)
+        .await?
+        .last_insert_rowid())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{undo_entries, Undo};
+    use crate::session_buddy::database::connect;
+    use crate::session_buddy::database::tests::scratch_db;
+
+    fn undo(register: &str) -> Undo {
+        Undo {
+            id: 1,
+            creation_date_time: Some(Utc::now()),
+            tab_identifier: None,
+            action: Some("deleteTab".to_string()),
+            description: Some("Deleted tab".to_string()),
+            register1: Some("not json".to_string()),
+            register2: Some(register.to_string()),
+            register3: None,
+            register4: None,
+            register5: None
+        }
+    }
+
+    #[test]
+    fn restores_tab_into_new_session() {
+        let session = undo(r#"{"url": "https://example.com/", "title": "Example"}"#)
+            .to_saved_session()
+            .unwrap();
+
+        assert_eq!(session.name, "Deleted tab");
+        assert_eq!(session.count_windows(), 1);
+        assert_eq!(session.count_tabs(), 1);
+    }
+
+    #[test]
+    fn restores_session() {
+        let session = undo(r#"{"name": "Work", "windows": "[{\"tabs\": [{}, {}]}]"}"#)
+            .to_saved_session()
+            .unwrap();
+
+        assert_eq!(session.name, "Work");
+        assert_eq!(session.count_tabs(), 2);
+    }
+
+    #[test]
+    fn fails_without_payload() {
+        assert!(undo("42").to_saved_session().is_err());
+    }
+
+    #[test]
+    fn fails_on_broken_session() {
+        let broken = undo(r#"{"name": 42, "windows": "[{\"tabs\": []}]"}"#);
+        assert!(broken.to_saved_session().is_err());
+    }
+
+    #[tokio::test]
+    async fn lists_entries_without_timestamp() {
+        let (_dir, db) = scratch_db().await;
+        sqlx::query("INSERT INTO Undo (creationDateTime, register1) VALUES (NULL, '[]')")
+            .execute(&mut connect(&db).await.unwrap())
+            .await
+            .unwrap();
+
+        let entries = undo_entries(&db).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].creation_date_time, None);
+    }
 }
@@ -0,0 +1,363 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use sqlx::Row;
+
+use crate::session_buddy::database::connect;
+use crate::util::format_bytes;
+use crate::Error;
+
+/// Storage used by a single column.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnUsage {
+    pub name: String,
+    pub bytes: u64
+}
+
+/// Storage used by a table and its columns.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TableUsage {
+    pub name: String,
+    pub rows: u64,
+    pub bytes: u64,
+    pub columns: Vec<ColumnUsage>
+}
+
+/// Storage used by a single session.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionUsage {
+    pub table: String,
+    pub id: i64,
+    pub name: Option<String>,
+    pub windows_bytes: u64,
+    pub thumbnail_bytes: u64
+}
+
+impl SessionUsage {
+    pub fn bytes(&self) -> u64 {
+        self.windows_bytes + self.thumbnail_bytes
+    }
+}
+
+/// Where the space of a database file goes. Sizes of tables and
+/// columns are the sizes of the stored values, without SQLite's
+/// overhead for pages and indices.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Usage {
+    pub path: PathBuf,
+    pub file_size: u64,
+    pub page_size: u64,
+    pub page_count: u64,
+    pub freelist_count: u64,
+    pub tables: Vec<TableUsage>,
+    pub largest_sessions: Vec<SessionUsage>
+}
+
+impl Usage {
+    /// Bytes of unused pages, which VACUUM gives back.
+    pub fn freelist_bytes(&self) -> u64 {
+        self.freelist_count * self.page_size
+    }
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Break down the storage of a database by table and column and
+/// find the top largest sessions.
+pub async fn usage(db: &Path, top: u32) -> Result<Usage, Error> {
+    let mut conn = connect(db).await?;
+
+    let page_size: i64 = sqlx::query_scalar("PRAGMA page_size")
+        .fetch_one(&mut conn)
+        .await?;
+    let page_count: i64 = sqlx::query_scalar("PRAGMA page_count")
+        .fetch_one(&mut conn)
+        .await?;
+    let freelist_count: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+        .fetch_one(&mut conn)
+        .await?;
+
+    let names: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name"
+    )
+    .fetch_all(&mut conn)
+    .await?;
+
+    let mut tables = vec![];
+
+    for name in names {
+        let columns: Vec<String> = sqlx::query(&format!("PRAGMA table_info({})", quote(&name)))
+            .fetch_all(&mut conn)
+            .await?
+            .iter()
+            .map(|row| row.try_get("name"))
+            .collect::<Result<_, _>>()?;
+
+        let sums = columns
+            .iter()
+            .map(|c| format!("COALESCE(SUM(LENGTH(CAST({} AS BLOB))), 0)", quote(c)))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let row = sqlx::query(&format!("SELECT COUNT(*), {} FROM {}", sums, quote(&name)))
+            .fetch_one(&mut conn)
+            .await?;
+
+        let mut table = TableUsage {
+            name,
+            rows: row.try_get::<i64, _>(0)? as u64,
+            bytes: 0,
+            columns: vec![]
+        };
+
+        for (i, column) in columns.into_iter().enumerate() {
+            let bytes = row.try_get::<i64, _>(i + 1)? as u64;
+            table.bytes += bytes;
+            table.columns.push(ColumnUsage {
+                name: column,
+                bytes
+            });
+        }
+
+        table.columns.sort_by_key(|c| Reverse(c.bytes));
+        tables.push(table);
+    }
+
+    tables.sort_by_key(|t| Reverse(t.bytes));
+
+    let largest_sessions = sqlx::query(
+        r#"
+        SELECT * FROM (
+            SELECT 'SavedSessions' AS tbl, id, name,
+                COALESCE(LENGTH(CAST(windows AS BLOB)), 0) AS w,
+                COALESCE(LENGTH(CAST(thumbnail AS BLOB)), 0) AS t
+            FROM SavedSessions
+            UNION ALL
+            SELECT 'PreviousSessions' AS tbl, id, NULL AS name,
+                COALESCE(LENGTH(CAST(windows AS BLOB)), 0) AS w,
+                COALESCE(LENGTH(CAST(thumbnail AS BLOB)), 0) AS t
+            FROM PreviousSessions
+        ) ORDER BY w + t DESC LIMIT $1
+        "#
+    )
+    .bind(top)
+    .fetch_all(&mut conn)
+    .await?
+    .iter()
+    .map(|row| {
+        Ok(SessionUsage {
+            table: row.try_get("tbl")?,
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            windows_bytes: row.try_get::<i64, _>("w")? as u64,
+            thumbnail_bytes: row.try_get::<i64, _>("t")? as u64
+        })
+    })
+    .collect::<Result<Vec<SessionUsage>, sqlx::Error>>()?;
+
+    Ok(Usage {
+        path: db.to_path_buf(),
+        file_size: fs::metadata(db)?.len(),
+        page_size: page_size as u64,
+        page_count: page_count as u64,
+        freelist_count: freelist_count as u64,
+        tables,
+        largest_sessions
+    })
+}
+
+fn with_suffix(db: &Path, suffix: &str) -> PathBuf {
+    let mut path = db.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// VACUUM a database through a snapshot: the compacted copy is
+/// written next to the database, checked for integrity and only
+/// then moved over the original, which is kept as `<db>.bak` until
+/// the move succeeded. Returns the file size before and after.
+/// Refuses to run while a `-wal` or `-journal` file exists, as the
+/// browser may have the database open or left changes behind.
+pub async fn compact(db: &Path) -> Result<(u64, u64), Error> {
+    for suffix in ["-wal", "-journal"] {
+        let sidecar = with_suffix(db, suffix);
+        if sidecar.exists() {
+            return Err(Error::Locked(format!(
+                "{} exists, close the browser before compacting",
+                sidecar.display()
+            )));
+        }
+    }
+
+    let metadata = fs::metadata(db)?;
+    let before = metadata.len();
+
+    let snapshot = with_suffix(db, ".compact");
+    if snapshot.exists() {
+        fs::remove_file(&snapshot)?;
+    }
+
+    let mut conn = connect(db).await?;
+    sqlx::query("VACUUM INTO ?")
+        .bind(snapshot.to_string_lossy())
+        .execute(&mut conn)
+        .await?;
+    drop(conn);
+
+    let mut conn = connect(&snapshot).await?;
+    let integrity: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(&mut conn)
+        .await?;
+    drop(conn);
+
+    if integrity != ["ok"] {
+        fs::remove_file(&snapshot)?;
+        return Err(Error::Format(format!(
+            "compacted snapshot failed the integrity check: {}",
+            integrity.join(", ")
+        )));
+    }
+
+    fs::set_permissions(&snapshot, metadata.permissions())?;
+
+    let backup = with_suffix(db, ".bak");
+    fs::rename(db, &backup)?;
+    if let Err(e) = fs::rename(&snapshot, db) {
+        fs::rename(&backup, db)?;
+        return Err(e.into());
+    }
+    fs::remove_file(&backup)?;
+
+    Ok((before, fs::metadata(db)?.len()))
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Path:        {}", self.path.display())?;
+        writeln!(f, "File size:   {:>10}", format_bytes(self.file_size))?;
+        writeln!(f, "Page size:   {:>10}", self.page_size)?;
+        writeln!(f, "Pages:       {:>10}", self.page_count)?;
+        writeln!(
+            f,
+            "Free pages:  {:>10} ({})",
+            self.freelist_count,
+            format_bytes(self.freelist_bytes())
+        )?;
+
+        writeln!(f)?;
+        writeln!(f, "{:<30} {:>10} {:>12}", "Table", "Rows", "Size")?;
+        for table in self.tables.iter() {
+            writeln!(
+                f,
+                "{:<30} {:>10} {:>12}",
+                table.name,
+                table.rows,
+                format_bytes(table.bytes)
+            )?;
+            for column in table.columns.iter().filter(|c| c.bytes > 0) {
+                writeln!(
+                    f,
+                    "  {:<39} {:>12}",
+                    column.name,
+                    format_bytes(column.bytes)
+                )?;
+            }
+        }
+
+        writeln!(f)?;
+        write!(
+            f,
+            "{:<30} {:>10} {:>12}  Name",
+            "Largest sessions", "Id", "Size"
+        )?;
+        for session in self.largest_sessions.iter() {
+            write!(
+                f,
+                "\n{:<30} {:>10} {:>12}  {}",
+                session.table,
+                session.id,
+                format_bytes(session.bytes()),
+                session.name.as_deref().unwrap_or_default()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::Utc;
+
+    use super::{compact, usage, with_suffix};
+    use crate::session_buddy::database::connect;
+    use crate::session_buddy::database::tests::scratch_db;
+    use crate::ErrorKind;
+
+    #[tokio::test]
+    async fn reports_usage_and_compacts() {
+        let (_dir, db) = scratch_db().await;
+        let mut conn = connect(&db).await.unwrap();
+        for (name, size) in [("small", 10), ("large", 100_000), ("gone", 200_000)] {
+            sqlx::query(
+                "INSERT INTO SavedSessions (name, creationDateTime, deleted, thumbnail, windows) VALUES ($1, $2, 'false', $3, '[]')"
+            )
+            .bind(name)
+            .bind(Utc::now())
+            .bind("x".repeat(size))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        }
+        sqlx::query("DELETE FROM SavedSessions WHERE name = 'gone'")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let report = usage(&db, 1).await.unwrap();
+        assert!(report.freelist_count > 0);
+        let saved = report
+            .tables
+            .iter()
+            .find(|t| t.name == "SavedSessions")
+            .unwrap();
+        assert_eq!(saved.rows, 2);
+        assert_eq!(saved.columns[0].name, "thumbnail");
+        assert_eq!(saved.columns[0].bytes, 100_010);
+        assert_eq!(report.largest_sessions.len(), 1);
+        assert_eq!(report.largest_sessions[0].name.as_deref(), Some("large"));
+        assert_eq!(report.largest_sessions[0].thumbnail_bytes, 100_000);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&db, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+
+        let wal = with_suffix(&db, "-wal");
+        fs::write(&wal, b"").unwrap();
+        assert_eq!(compact(&db).await.unwrap_err().kind(), ErrorKind::Locked);
+        fs::remove_file(&wal).unwrap();
+
+        let (before, after) = compact(&db).await.unwrap();
+        assert!(after < before);
+        assert!(!with_suffix(&db, ".compact").exists());
+        assert!(!with_suffix(&db, ".bak").exists());
+        assert_eq!(usage(&db, 1).await.unwrap().freelist_count, 0);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&db).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}